@@ -0,0 +1,24 @@
+// Sorts the rows of .sortable tables by the clicked column, using the
+// data-sort attribute of cells: numbers as numbers, and empty cells last.
+// Clicking again reverses the order.
+$(function() {
+	$('.sortable th:not(.unsortable)').click(function() {
+		var table = $(this).closest('table');
+		var column = $(this).index();
+		var ascending = !$(this).hasClass('ascending');
+		table.find('th').removeClass('ascending descending');
+		$(this).addClass(ascending ? 'ascending' : 'descending');
+
+		var rows = table.find('tbody tr').get();
+		rows.sort(function(a, b) {
+			var x = $(a).children().eq(column).attr('data-sort');
+			var y = $(b).children().eq(column).attr('data-sort');
+			if (x === '' || y === '')
+				return (x === '') - (y === '');
+			var order = $.isNumeric(x) && $.isNumeric(y) ?
+				parseFloat(x) - parseFloat(y) : x.localeCompare(y);
+			return ascending ? order : -order;
+		});
+		table.children('tbody').append(rows);
+	});
+});
@@ -1,16 +1,289 @@
+use std::cmp::Ordering;
+use std::error::Error;
 use std::f32;
+use std::fmt;
 use std::fs;
-use std::path;
 use std::io;
-use std::io::BufRead;
+use std::io::{BufRead, Read};
+use std::path;
+use std::str;
 use std::str::FromStr;
-use std::cmp::Ordering;
+use std::sync::OnceLock;
+use std::thread;
+pub use column::Bag;
+use column::{Column, Table};
+use date::DateFormat;
+use decompress;
+use tokenizer;
+use tokenizer::{Scanner, SyntaxError, Token};
+
+/// What went wrong while loading an arff file.
+#[derive(Debug)]
+pub enum ArffErrorKind {
+    /// The file could not be opened or read.
+    Io(io::Error),
+    /// A line could not be split into tokens.
+    Syntax(&'static str),
+    /// A header line is missing a required part.
+    BadHeader(&'static str),
+    /// The attribute type is not one we know.
+    BadType,
+    /// A numeric value could not be parsed.
+    BadNumber,
+    /// A date value does not match the attribute format.
+    BadDate,
+    /// A date format uses unsupported fields.
+    BadDateFormat,
+    /// A nominal value is not among the declared tokens.
+    UndeclaredToken,
+    /// An instance weight is not a non-negative number.
+    BadWeight,
+    /// A sparse row refers to an attribute that does not exist, or
+    /// lists attributes out of order.
+    BadIndex,
+    /// A data line does not have one value per attribute.
+    FieldCount { expected: usize, found: usize },
+    /// A row inside a relational value is invalid.
+    BadBag(Box<ArffError>),
+    /// An XRFF document is not well-formed, or misses required parts.
+    BadXml(String),
+    /// A value given to `ArffBuilder` does not match its attribute type.
+    WrongType,
+    /// A column given on the command line is not in the file.
+    UnknownColumn,
+    /// The class refers to an attribute that does not exist.
+    BadClass,
+}
+
+/// Error returned when an arff file cannot be loaded.
+///
+/// Carries as much location information as is available: the file, the
+/// (1-based) line and column, the attribute involved and the bad token.
+#[derive(Debug)]
+pub struct ArffError {
+    pub filename: String,
+    pub line: Option<usize>,
+    pub column: Option<u32>,
+    pub attribute: Option<String>,
+    pub token: Option<String>,
+    pub kind: ArffErrorKind,
+}
+
+impl ArffError {
+    /// Creates an error, with no location yet.
+    pub fn new(kind: ArffErrorKind) -> Self {
+        ArffError {
+            filename: String::new(),
+            line: None,
+            column: None,
+            attribute: None,
+            token: None,
+            kind,
+        }
+    }
+
+    // Records where in the line the problem is.
+    fn at(mut self, line: &str, offset: usize) -> Self {
+        self.column = Some(line[..offset].chars().count() as u32 + 1);
+        self
+    }
+
+    // Records the offending token, and where it starts in the line.
+    fn with_token(self, line: &str, token: &Token) -> Self {
+        let mut error = self.at(line, token.offset);
+        error.token = Some(token.text.to_string());
+        error
+    }
+
+    fn syntax(line: &str, error: SyntaxError) -> Self {
+        ArffError::new(ArffErrorKind::Syntax(error.message))
+            .at(line, error.offset)
+    }
+
+    /// Records the attribute the problem is about.
+    pub fn with_attribute(mut self, name: &str) -> Self {
+        self.attribute = Some(name.to_string());
+        self
+    }
+
+    /// Records the file and (1-based) line of the problem.
+    pub fn located(mut self, filename: &str, line: Option<usize>) -> Self {
+        self.filename = filename.to_string();
+        self.line = line;
+        self
+    }
+}
+
+impl fmt::Display for ArffError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.filename)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        write!(f, ": ")?;
+        self.fmt_message(f)
+    }
+}
+
+impl ArffError {
+    // Describes the problem, without its location.
+    fn fmt_message(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let token = self.token.as_deref().unwrap_or("");
+        match self.kind {
+            ArffErrorKind::Io(ref e) => write!(f, "{}", e)?,
+            ArffErrorKind::Syntax(what) => write!(f, "{}", what)?,
+            ArffErrorKind::BadHeader(what) => write!(f, "{}", what)?,
+            ArffErrorKind::BadType => write!(f, "bad type `{}`", token)?,
+            ArffErrorKind::BadNumber => write!(f, "bad number `{}`", token)?,
+            ArffErrorKind::BadDate => write!(f, "bad date `{}`", token)?,
+            ArffErrorKind::BadDateFormat => {
+                write!(f, "unsupported date format `{}`", token)?
+            }
+            ArffErrorKind::UndeclaredToken => {
+                write!(f, "undeclared nominal value `{}`", token)?
+            }
+            ArffErrorKind::BadWeight => {
+                write!(f, "bad instance weight `{}`", token)?
+            }
+            ArffErrorKind::BadIndex => {
+                write!(f, "bad sparse attribute index `{}`", token)?
+            }
+            ArffErrorKind::FieldCount { expected, found } => {
+                write!(f, "expected {} values, found {}", expected, found)?
+            }
+            ArffErrorKind::BadXml(ref what) => write!(f, "bad xrff: {}", what)?,
+            ArffErrorKind::WrongType => {
+                write!(f, "{} value does not match the attribute type", token)?
+            }
+            ArffErrorKind::UnknownColumn => write!(f, "no column `{}`", token)?,
+            ArffErrorKind::BadClass => write!(f, "no attribute {} for the class", token)?,
+            ArffErrorKind::BadBag(ref inner) => {
+                write!(f, "in bag row {}: ", inner.line.unwrap_or(0))?;
+                inner.fmt_message(f)?
+            }
+        }
+
+        if let Some(ref attribute) = self.attribute {
+            write!(f, " (attribute `{}`)", attribute)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ArffError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.kind {
+            ArffErrorKind::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// How to react to bad values in the data section.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseMode {
+    /// Fail on the first problem.
+    Strict,
+    /// Replace bad values with `Value::Missing`, skip malformed rows, and
+    /// record every problem in `ArffContent::diagnostics`.
+    Lenient,
+}
+
+/// A problem worked around while loading in lenient mode.
+pub struct Diagnostic {
+    pub error: ArffError,
+    /// The offending line, as found in the file.
+    pub text: String,
+}
+
+/// Number of problems kept for each attribute, the others being only
+/// counted.
+pub const DIAGNOSTIC_SAMPLES: usize = 5;
+
+/// Problems found with an attribute, or with whole rows.
+pub struct DiagnosticGroup {
+    /// `None` for problems with whole rows.
+    pub attribute: Option<String>,
+    /// Number of problems found.
+    pub count: usize,
+    /// The first `DIAGNOSTIC_SAMPLES` problems, in file order.
+    pub samples: Vec<Diagnostic>,
+}
+
+/// Problems found while loading in lenient mode, grouped by attribute.
+///
+/// Only a few lines are kept for each attribute, so that a systematically
+/// bad column does not copy the whole file in memory.
+#[derive(Default)]
+pub struct Diagnostics {
+    groups: Vec<DiagnosticGroup>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Group of the given attribute, created if needed.
+    fn group(&mut self, attribute: &Option<String>) -> &mut DiagnosticGroup {
+        let i = match self.groups.iter().position(|group| group.attribute == *attribute) {
+            Some(i) => i,
+            None => {
+                self.groups.push(DiagnosticGroup {
+                    attribute: attribute.clone(),
+                    count: 0,
+                    samples: Vec::new(),
+                });
+                self.groups.len() - 1
+            }
+        };
+        &mut self.groups[i]
+    }
+
+    /// Records a problem.
+    pub(crate) fn push(&mut self, diagnostic: Diagnostic) {
+        let group = self.group(&diagnostic.error.attribute);
+        group.count += 1;
+        if group.samples.len() < DIAGNOSTIC_SAMPLES {
+            group.samples.push(diagnostic);
+        }
+    }
+
+    /// Adds the problems of `other`, found after those of `self`.
+    pub(crate) fn append(&mut self, other: Diagnostics) {
+        for other in other.groups {
+            let group = self.group(&other.attribute);
+            group.count += other.count;
+            let room = DIAGNOSTIC_SAMPLES.saturating_sub(group.samples.len());
+            group.samples.extend(other.samples.into_iter().take(room));
+        }
+    }
+
+    /// Groups, in order of their first problem.
+    pub fn groups(&self) -> &[DiagnosticGroup] {
+        &self.groups
+    }
+
+    /// Total number of problems.
+    pub fn len(&self) -> usize {
+        self.groups.iter().map(|group| group.count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
 
 #[derive(Serialize, Deserialize)]
 pub struct Population(pub Vec<usize>);
 
 pub enum AttributeSamples {
     Numeric(Vec<(f32, usize)>),
+    Date(Vec<(i64, usize)>),
     Text(Vec<Population>),
     BadType,
 }
@@ -18,7 +291,11 @@ pub enum AttributeSamples {
 impl AttributeSamples {
     fn from_attr(attr: &Attribute) -> Self {
         match attr.att_type {
-            AttributeType::Numeric => AttributeSamples::Numeric(Vec::new()),
+            // Relational attributes are viewed by bag size
+            AttributeType::Numeric |
+            AttributeType::Integer |
+            AttributeType::Relational(_) => AttributeSamples::Numeric(Vec::new()),
+            AttributeType::Date(_) => AttributeSamples::Date(Vec::new()),
             AttributeType::Text(ref tokens) => {
                 let mut list = Vec::with_capacity(tokens.len());
                 for _ in 0..tokens.len() {
@@ -31,78 +308,356 @@ impl AttributeSamples {
     }
 }
 
-pub struct Instance {
-    pub values: Vec<Value>,
+// Size of the blocks of data lines parsed in parallel
+const BLOCK_SIZE: u64 = 64 << 20;
+
+// Returns `true` if the data line uses the sparse `{index value, ...}` form.
+fn is_sparse(line: &str) -> bool {
+    line.trim_start().starts_with('{')
+}
+
+// Parses a data line, dense or sparse, and adds it to the table.
+// Nothing is added if the row should be skipped.
+fn parse_row(table: &mut Table, attributes: &[Attribute], line: &str,
+             mode: ParseMode, problems: &mut Vec<ArffError>)
+             -> Result<(), ArffError> {
+    if is_sparse(line) {
+        parse_sparse(table, attributes, line, mode, problems)
+    } else {
+        parse_dense(table, attributes, line, mode, problems)
+    }
+}
+
+// Parses the instance weight, if any. Defaults to 1.
+fn parse_weight(line: &str, token: Option<Token>, mode: ParseMode,
+                problems: &mut Vec<ArffError>)
+                -> Result<f32, ArffError> {
+    let token = match token {
+        Some(token) => token,
+        None => return Ok(1.0),
+    };
+    match f32::from_str(&token.text) {
+        Ok(weight) if weight >= 0.0 && weight.is_finite() => Ok(weight),
+        _ => {
+            let error = ArffError::new(ArffErrorKind::BadWeight)
+                .with_token(line, &token);
+            report(mode, problems, error)?;
+            Ok(1.0)
+        }
+    }
+}
+
+// Parses a value into its column, which gets a missing value if it is
+// invalid.
+fn parse_value(table: &mut Table, attr: &Attribute, att: usize, line: &str,
+               token: &Token, mode: ParseMode, problems: &mut Vec<ArffError>)
+               -> Result<(), ArffError> {
+    let row = table.len();
+    let column = table.column_mut(att);
+    if let Err(kind) = attr.att_type.parse_value(token, column, row) {
+        column.push_missing(row);
+        let error = ArffError::new(kind)
+            .with_token(line, token)
+            .with_attribute(&attr.name);
+        report(mode, problems, error)?;
+    }
+    Ok(())
+}
+
+fn parse_dense(table: &mut Table, attributes: &[Attribute], line: &str,
+               mode: ParseMode, problems: &mut Vec<ArffError>)
+               -> Result<(), ArffError> {
+    let (tokens, weight) = match tokenizer::fields(line) {
+        Ok(row) => row,
+        Err(e) => return report(mode, problems, ArffError::syntax(line, e)),
+    };
+    // Nothing but a comment
+    if tokens.is_empty() {
+        return Ok(());
+    }
+
+    if tokens.len() != attributes.len() {
+        let kind = ArffErrorKind::FieldCount {
+            expected: attributes.len(),
+            found: tokens.len(),
+        };
+        // Skip the whole row
+        return report(mode, problems, ArffError::new(kind));
+    }
+
+    for (att, (token, attr)) in tokens.iter().zip(attributes.iter()).enumerate() {
+        parse_value(table, attr, att, line, token, mode, problems)?;
+    }
+
+    let weight = parse_weight(line, weight, mode, problems)?;
+    table.end_row(weight);
+    Ok(())
+}
+
+/// Adds the values left out of a sparse row to the current row, for the
+/// attributes in `from..to`. Only dense tables store them.
+pub(crate) fn fill_sparse_defaults(table: &mut Table, attributes: &[Attribute],
+                            from: usize, to: usize) {
+    if table.is_sparse() {
+        return;
+    }
+    let row = table.len();
+    for (att, attr) in attributes.iter().enumerate().take(to).skip(from) {
+        let column = table.column_mut(att);
+        match attr.att_type.sparse_default() {
+            Value::Numeric(f) => column.push_num(row, f),
+            Value::Date(d) => column.push_date(row, d),
+            Value::Text(i) => column.push_token(row, i),
+            _ => column.push_missing(row),
+        }
+    }
+}
+
+fn parse_sparse(table: &mut Table, attributes: &[Attribute], line: &str,
+                mode: ParseMode, problems: &mut Vec<ArffError>)
+                -> Result<(), ArffError> {
+    let (pairs, weight) = match tokenizer::sparse_fields(line) {
+        Ok(row) => row,
+        Err(e) => return report(mode, problems, ArffError::syntax(line, e)),
+    };
+
+    let mut next = 0;
+    for (index, token) in pairs {
+        // Indices must be valid, and in increasing order
+        let att = match usize::from_str(&index.text) {
+            Ok(att) if att >= next && att < attributes.len() => att,
+            _ => {
+                let error = ArffError::new(ArffErrorKind::BadIndex)
+                    .with_token(line, &index);
+                report(mode, problems, error)?;
+                continue;
+            }
+        };
+
+        fill_sparse_defaults(table, attributes, next, att);
+        parse_value(table, &attributes[att], att, line, &token, mode, problems)?;
+        next = att + 1;
+    }
+    fill_sparse_defaults(table, attributes, next, attributes.len());
+
+    let weight = parse_weight(line, weight, mode, problems)?;
+    table.end_row(weight);
+    Ok(())
 }
 
-pub enum Value {
+#[derive(Clone, Copy)]
+pub enum Value<'a> {
     Numeric(f32),
+    // Milliseconds since the epoch
+    Date(i64),
     Text(usize),
-    String(String),
+    String(&'a str),
+    // Sub-instances of a relational attribute
+    Relational(Bag<'a>),
     Missing,
 }
 
-impl Value {
+impl<'a> Value<'a> {
     pub fn num(&self) -> Option<f32> {
-        match self {
-            &Value::Numeric(f) => Some(f),
+        match *self {
+            Value::Numeric(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    pub fn date(&self) -> Option<i64> {
+        match *self {
+            Value::Date(d) => Some(d),
             _ => None,
         }
     }
 
     pub fn text(&self) -> Option<usize> {
-        match self {
-            &Value::Text(i) => Some(i),
+        match *self {
+            Value::Text(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn bag(&self) -> Option<Bag<'a>> {
+        match *self {
+            Value::Relational(bag) => Some(bag),
             _ => None,
         }
     }
 
-    pub fn string(&self) -> Option<&str> {
-        match self {
-            &Value::String(ref s) => Some(s),
+    pub fn string(&self) -> Option<&'a str> {
+        match *self {
+            Value::String(s) => Some(s),
             _ => None,
         }
     }
 }
 
 pub enum AttributeType {
+    // Declared as `numeric` or `real`
     Numeric,
+    // Declared as `integer`
+    Integer,
+    Date(DateFormat),
     Text(Vec<String>),
     String,
-    Unknown,
+    // Nested schema of a multi-instance bag
+    Relational(Vec<Attribute>),
 }
 
 impl AttributeType {
     // Parse an attribute type from the arff header
-    fn parse(s: &str) -> Self {
-        if s == "numeric" {
-            return AttributeType::Numeric;
+    fn parse(line: &str, scanner: &mut Scanner) -> Result<Self, ArffError> {
+        if scanner.eat('{') {
+            let mut tokens = Vec::new();
+            if !scanner.eat('}') {
+                loop {
+                    let token = scanner.word()
+                        .map_err(|e| ArffError::syntax(line, e))?;
+                    tokens.push(token.text.into_owned());
+                    if scanner.eat('}') {
+                        break;
+                    }
+                    scanner.expect(',', "expected `,` or `}`")
+                        .map_err(|e| ArffError::syntax(line, e))?;
+                }
+            }
+            return Ok(AttributeType::Text(tokens));
+        }
+
+        let token = scanner.word().map_err(|e| ArffError::syntax(line, e))?;
+        match &*token.text.to_lowercase() {
+            "numeric" | "real" => Ok(AttributeType::Numeric),
+            "integer" => Ok(AttributeType::Integer),
+            "string" => Ok(AttributeType::String),
+            // Nested attributes are declared on the following lines
+            "relational" => Ok(AttributeType::Relational(Vec::new())),
+            "date" => {
+                if scanner.at_end() {
+                    return Ok(AttributeType::Date(DateFormat::iso()));
+                }
+                let pattern = scanner.word()
+                    .map_err(|e| ArffError::syntax(line, e))?;
+                match DateFormat::from_pattern(&pattern.text) {
+                    Some(format) => Ok(AttributeType::Date(format)),
+                    None => {
+                        Err(ArffError::new(ArffErrorKind::BadDateFormat)
+                                .with_token(line, &pattern))
+                    }
+                }
+            }
+            _ => {
+                Err(ArffError::new(ArffErrorKind::BadType)
+                        .with_token(line, &token))
+            }
         }
-        if s == "string" {
-            return AttributeType::String;
+    }
+
+    /// Parses a single value of this type, and adds it to the column.
+    /// Nothing is added on error.
+    pub(crate) fn parse_value(&self, token: &Token, column: &mut Column, row: usize)
+                   -> Result<(), ArffErrorKind> {
+        if token.is_missing() {
+            column.push_missing(row);
+            return Ok(());
+        }
+
+        match *self {
+            AttributeType::Numeric | AttributeType::Integer => {
+                column.push_num(row, parse_f32(&token.text)?)
+            }
+            AttributeType::Date(ref format) => {
+                let date = format.parse(&token.text).ok_or(ArffErrorKind::BadDate)?;
+                column.push_date(row, date)
+            }
+            AttributeType::Text(ref tokens) => {
+                let index = tokens.iter()
+                    .position(|s| *s == token.text)
+                    .ok_or(ArffErrorKind::UndeclaredToken)?;
+                column.push_token(row, index)
+            }
+            AttributeType::String => column.push_str(row, &token.text),
+            AttributeType::Relational(ref attributes) => {
+                // One sub-instance per line
+                let mut bag = Table::new(attributes, false);
+                for (i, line) in token.text.split('\n').enumerate() {
+                    let line = line.trim_end_matches('\r');
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    parse_row(&mut bag, attributes, line, ParseMode::Strict, &mut Vec::new())
+                        .map_err(|e| {
+                            ArffErrorKind::BadBag(Box::new(e.located("", Some(i + 1))))
+                        })?;
+                }
+                column.push_bag(row, bag)
+            }
         }
-        if s.len() < 2 {
-            println!("Bad type: `{}`", s);
-            return AttributeType::Unknown;
+        Ok(())
+    }
+
+    /// Writes a value of this type as it would appear in an arff file.
+    pub fn describe(&self, value: Value) -> String {
+        match value {
+            Value::Numeric(f) if f == f32::INFINITY => "Infinity".to_string(),
+            Value::Numeric(f) if f == f32::NEG_INFINITY => "-Infinity".to_string(),
+            Value::Numeric(f) => format!("{}", f),
+            Value::Date(d) => match *self {
+                AttributeType::Date(ref format) => {
+                    tokenizer::quote(&format.format(d)).into_owned()
+                }
+                _ => d.to_string(),
+            },
+            Value::Text(i) => match self.tokens() {
+                Some(tokens) => tokenizer::quote(&tokens[i]).into_owned(),
+                None => "?".to_string(),
+            },
+            Value::String(s) => tokenizer::quote(s).into_owned(),
+            Value::Relational(bag) => match *self {
+                AttributeType::Relational(ref attributes) => {
+                    let rows: Vec<_> = (0..bag.len())
+                        .map(|i| bag.describe(attributes, i))
+                        .collect();
+                    tokenizer::quote(&rows.join("\n")).into_owned()
+                }
+                _ => "?".to_string(),
+            },
+            Value::Missing => "?".to_string(),
         }
+    }
 
-        let mut chars = s.chars();
-        if chars.next().unwrap() != '{' || chars.last().unwrap() != '}' {
-            panic!("Bad type: `{}`", s);
+    /// Value taken by an attribute of this type when left out of a sparse
+    /// row: zero, or the first declared token for nominal attributes.
+    pub fn sparse_default(&self) -> Value<'static> {
+        match *self {
+            AttributeType::Numeric | AttributeType::Integer => Value::Numeric(0.0),
+            AttributeType::Date(_) => Value::Date(0),
+            AttributeType::Text(ref tokens) if !tokens.is_empty() => {
+                Value::Text(0)
+            }
+            _ => Value::Missing,
         }
+    }
 
-        let tokens = {
-            let len = s.len();
-            s[1..len - 1].split(',').map(|s| s.to_string()).collect()
-        };
-        AttributeType::Text(tokens)
+    /// Returns `true` if the attribute was declared as `integer`.
+    pub fn is_integer(&self) -> bool {
+        matches!(*self, AttributeType::Integer)
+    }
+
+    /// If the type is relational, returns the nested attributes.
+    pub fn relation(&self) -> Option<&[Attribute]> {
+        match *self {
+            AttributeType::Relational(ref attributes) => Some(attributes),
+            _ => None,
+        }
     }
 
     /// If the type is numeric, returns the list of tokens.
     /// Returns None otherwise.
     pub fn tokens(&self) -> Option<&[String]> {
-        match self {
-            &AttributeType::Text(ref tokens) => Some(tokens),
+        match *self {
+            AttributeType::Text(ref tokens) => Some(tokens),
             _ => None,
         }
     }
@@ -117,166 +672,607 @@ pub struct ArffContent {
     pub filename: String,
     pub title: String,
 
-    // List of all data points
-    pub data: Vec<Instance>,
+    // All data points, stored by attribute
+    pub(crate) data: Table,
     // List of attributes from the header
-    pub attributes: Vec<Attribute>,
-    // Per-attribute list of samples
-    pub samples: Vec<AttributeSamples>,
+    attributes: Vec<Attribute>,
+    // Per-attribute list of samples, computed on demand
+    samples: Vec<OnceLock<AttributeSamples>>,
+    // Problems found while loading in lenient mode
+    diagnostics: Diagnostics,
+    // Class attribute, when the file tells which one it is
+    class_attribute: Option<usize>,
+
+    // Relational attributes still being declared, innermost last
+    open_relations: Vec<Attribute>,
 }
 
-fn parse_f32(s: &str) -> f32 {
+/// An instance of a dataset, as returned by `ArffContent::instances`.
+#[derive(Clone, Copy)]
+pub struct Instance<'a> {
+    content: &'a ArffContent,
+    row: usize,
+}
+
+impl<'a> Instance<'a> {
+    /// Position of the instance in the dataset.
+    pub fn index(&self) -> usize {
+        self.row
+    }
+
+    pub fn weight(&self) -> f32 {
+        self.content.data.weight(self.row)
+    }
+
+    /// Returns the value of the `att`-th attribute.
+    pub fn value(&self, att: usize) -> Value<'a> {
+        self.content.value(self.row, att)
+    }
+
+    /// Returns the value of the attribute with the given name.
+    pub fn get(&self, name: &str) -> Option<Value<'a>> {
+        self.content.attribute_id(name).map(|att| self.value(att))
+    }
+
+    /// Iterates over the values, in attribute order.
+    pub fn values(&self) -> impl ExactSizeIterator<Item = Value<'a>> + 'a {
+        let instance = *self;
+        (0..self.content.attributes.len()).map(move |att| instance.value(att))
+    }
+
+    /// Writes the instance as it would appear in an arff file.
+    pub fn describe(&self) -> String {
+        self.content.data.describe(&self.content.attributes, self.row)
+    }
+}
+
+// In strict mode, fails with `error`. In lenient mode, records it.
+pub(crate) fn report(mode: ParseMode, problems: &mut Vec<ArffError>,
+                         error: ArffError)
+                         -> Result<(), ArffError> {
+    match mode {
+        ParseMode::Strict => Err(error),
+        ParseMode::Lenient => {
+            problems.push(error);
+            Ok(())
+        }
+    }
+}
+
+/// Returns `name`, with underscores appended while an attribute already
+/// has that name.
+pub(crate) fn unique_name(attributes: &[Attribute], name: &str) -> String {
+    let mut name = name.to_string();
+    while attributes.iter().any(|attr| attr.name == name) {
+        name.push('_');
+    }
+    name
+}
+
+/// Parses a number, accepting Weka's `Infinity` and `-Infinity`.
+pub fn parse_f32(s: &str) -> Result<f32, ArffErrorKind> {
     if s == "Infinity" {
-        f32::INFINITY
+        Ok(f32::INFINITY)
     } else if s == "-Infinity" {
-        f32::NEG_INFINITY
+        Ok(f32::NEG_INFINITY)
     } else {
-        f32::from_str(s).ok().expect(&format!("Reading {}", s))
+        f32::from_str(s).map_err(|_| ArffErrorKind::BadNumber)
     }
 }
 
 impl ArffContent {
+    /// Wraps instances loaded from another format.
+    pub(crate) fn from_table(filename: &str, title: &str, attributes: Vec<Attribute>,
+                      data: Table)
+                      -> ArffContent {
+        ArffContent {
+            filename: filename.to_string(),
+            title: title.to_string(),
+            samples: attributes.iter().map(|_| OnceLock::new()).collect(),
+            attributes,
+            data,
+            diagnostics: Diagnostics::new(),
+            class_attribute: None,
+            open_relations: Vec::new(),
+        }
+    }
+
+    /// The attributes, as declared in the header.
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    /// Problems found while loading in lenient mode.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    pub(crate) fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        self.diagnostics = diagnostics;
+    }
+
+    /// The class attribute, when the file tells which one it is.
+    pub fn class_attribute(&self) -> Option<usize> {
+        self.class_attribute
+    }
+
+    /// Sets the class attribute, which must exist.
+    pub fn set_class_attribute(&mut self, class: Option<usize>) -> Result<(), ArffError> {
+        if let Some(att) = class.filter(|&att| att >= self.attributes.len()) {
+            let mut error = ArffError::new(ArffErrorKind::BadClass);
+            error.token = Some(att.to_string());
+            return Err(error.located(&self.filename, None));
+        }
+        self.class_attribute = class;
+        Ok(())
+    }
+
+    /// The attribute to compare others to by default: the class if known,
+    /// else the last attribute.
+    pub fn default_class(&self) -> usize {
+        self.class_attribute.unwrap_or(self.attributes.len() - 1)
+    }
 
     pub fn get_class_id(&self, attribute: usize, class: &str) -> Option<usize> {
         self.attributes[attribute]
             .att_type
             .tokens()
-            .and_then(|tokens| tokens.iter().enumerate().find(|&(_, value)| value == class))
-            .map(|(i, _)| i)
+            .and_then(|tokens| tokens.iter().position(|value| value == class))
     }
 
-    pub fn describe_sample(&self, sample_id: usize) -> String {
-        let mut line = String::new();
+    /// Returns the value of an attribute for the given sample.
+    pub fn value(&self, sample_id: usize, att: usize) -> Value<'_> {
+        self.data.value(&self.attributes, sample_id, att)
+    }
+
+    /// Samples of the given attribute, sorted by value.
+    ///
+    /// They are computed the first time they are needed, so that wide
+    /// datasets only pay for the attributes actually looked at.
+    pub fn samples(&self, att: usize) -> &AttributeSamples {
+        self.samples[att].get_or_init(|| self.make_samples(att))
+    }
 
-        for (value, attr) in self.data[sample_id].values.iter().zip(self.attributes.iter()) {
+    /// Calls `f` with the samples of the given attribute, computing them
+    /// without keeping them if they are not cached yet.
+    pub(crate) fn with_samples<T, F>(&self, att: usize, f: F) -> T
+        where F: FnOnce(&AttributeSamples) -> T
+    {
+        match self.samples[att].get() {
+            Some(samples) => f(samples),
+            None => f(&self.make_samples(att)),
+        }
+    }
+
+    pub fn describe_sample(&self, sample_id: usize) -> String {
+        for (att, attr) in self.attributes.iter().enumerate() {
             if attr.name == "id.ignore" {
-                if let Some(s) = value.string() {
+                if let Some(s) = self.value(sample_id, att).string() {
                     return s.to_string();
                 }
             }
-            match value {
-                &Value::Numeric(f) => line.push_str(&format!("{}", f)),
-                &Value::Text(i) => line.push_str(&attr.att_type.tokens().unwrap()[i]),
-                &Value::String(ref s) => line.push_str(s),
-                &Value::Missing => line.push('?'),
+        }
+
+        self.data.describe(&self.attributes, sample_id)
+    }
+
+    /// Returns `true` if some instances were given a weight other than 1.
+    pub fn has_weights(&self) -> bool {
+        self.data.has_weights()
+    }
+
+    /// Number of instances.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the `row`-th instance.
+    pub fn instance(&self, row: usize) -> Instance<'_> {
+        Instance { content: self, row }
+    }
+
+    /// Iterates over the instances, in file order.
+    pub fn instances(&self) -> impl ExactSizeIterator<Item = Instance<'_>> {
+        (0..self.len()).map(move |row| self.instance(row))
+    }
+
+    /// Returns the index of the attribute with the given name.
+    pub fn attribute_id(&self, name: &str) -> Option<usize> {
+        self.attributes.iter().position(|attr| attr.name == name)
+    }
+
+    /// Returns the attribute with the given name.
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attribute_id(name).map(|att| &self.attributes[att])
+    }
+
+    /// Values of a numeric (or integer) attribute for all instances, `None`
+    /// when missing.
+    ///
+    /// Returns `None` if the attribute is of another type. The other typed
+    /// accessors work the same way.
+    pub fn numeric_column(&self, att: usize)
+                          -> Option<impl ExactSizeIterator<Item = Option<f32>> + '_> {
+        match self.attributes[att].att_type {
+            AttributeType::Numeric | AttributeType::Integer => (),
+            _ => return None,
+        }
+        Some((0..self.len()).map(move |row| self.value(row, att).num()))
+    }
+
+    /// Values of a date attribute, in milliseconds since the epoch.
+    pub fn date_column(&self, att: usize)
+                       -> Option<impl ExactSizeIterator<Item = Option<i64>> + '_> {
+        match self.attributes[att].att_type {
+            AttributeType::Date(_) => (),
+            _ => return None,
+        }
+        Some((0..self.len()).map(move |row| self.value(row, att).date()))
+    }
+
+    /// Values of a nominal attribute, as indices in its tokens.
+    pub fn nominal_column(&self, att: usize)
+                          -> Option<impl ExactSizeIterator<Item = Option<usize>> + '_> {
+        self.attributes[att].att_type.tokens()?;
+        Some((0..self.len()).map(move |row| self.value(row, att).text()))
+    }
+
+    /// Values of a string attribute.
+    pub fn string_column(&self, att: usize)
+                         -> Option<impl ExactSizeIterator<Item = Option<&str>> + '_> {
+        match self.attributes[att].att_type {
+            AttributeType::String => (),
+            _ => return None,
+        }
+        Some((0..self.len()).map(move |row| self.value(row, att).string()))
+    }
+
+    // Parses the data lines of a chunk into a table of their own, along
+    // with the problems worked around in lenient mode.
+    fn parse_chunk(&self, text: &str, first_line: usize, mode: ParseMode)
+                   -> Result<(Table, Diagnostics), ArffError> {
+        let mut table = Table::new(&self.attributes, self.data.is_sparse());
+        let mut diagnostics = Diagnostics::new();
+
+        for (i, line) in text.lines().enumerate() {
+            if line.starts_with('%') || line.trim().is_empty() {
+                continue;
+            }
+            let line_no = Some(first_line + i);
+            let mut problems = Vec::new();
+            parse_row(&mut table, &self.attributes, line, mode, &mut problems)
+                .map_err(|e| e.located(&self.filename, line_no))?;
+            for error in problems {
+                diagnostics.push(Diagnostic {
+                    error: error.located(&self.filename, line_no),
+                    text: line.to_string(),
+                });
+            }
+        }
+        Ok((table, diagnostics))
+    }
+
+    // Parses a block of whole data lines on all cores, keeping rows in
+    // order.
+    fn load_block(&mut self, text: &str, first_line: usize, mode: ParseMode)
+                  -> Result<(), ArffError> {
+        // The first row tells whether to store the data sparsely
+        if self.data.is_empty() && !self.data.is_sparse() {
+            let first = text.lines()
+                .find(|line| !line.starts_with('%') && !line.trim().is_empty());
+            if first.is_some_and(is_sparse) {
+                self.data = Table::new(&self.attributes, true);
+            }
+        }
+
+        // Cut the block into chunks of about the same size, on line
+        // boundaries
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let mut chunks = Vec::with_capacity(threads);
+        let mut start = 0;
+        let mut line = first_line;
+        for k in 1..=threads {
+            let end = if k == threads {
+                text.len()
+            } else {
+                let target = (text.len() * k / threads).max(start);
+                text.as_bytes()[target..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(text.len(), |i| target + i + 1)
             };
-            line.push(',');
-        }
-
-        line
-    }
-
-    fn load_data_line(&mut self, line: &str) {
-        let values = line.split(',')
-                         .zip(self.attributes.iter())
-                         .map(|(token, attr)| {
-                             if token == "?" {
-                                 Value::Missing
-                             } else {
-                                 match attr.att_type {
-                                     AttributeType::Numeric => Value::Numeric(parse_f32(token)),
-                                     AttributeType::Text(ref tokens) =>
-                                         Value::Text(tokens.iter()
-                                                           .position(|s| s == token)
-                                                           .unwrap()),
-                                     AttributeType::String => Value::String(token.to_string()),
-                                     _ => Value::Missing,
-                                 }
-                             }
-                         })
-                         .collect();
-        self.data.push(Instance { values: values });
-    }
-
-    fn load_line(&mut self, line: &str) -> bool {
-        let mut tokens = line.split(' ');
-        match tokens.next() {
-            Some("@relation") => self.title = tokens.next().unwrap().to_string(),
-            Some("@attribute") => {
-                let name = tokens.next().unwrap();
-                let t = tokens.next().unwrap();
+            let chunk = &text[start..end];
+            chunks.push((chunk, line));
+            line += chunk.matches('\n').count();
+            start = end;
+        }
+
+        let results: Vec<_> = {
+            let this = &*self;
+            thread::scope(|scope| {
+                let handles: Vec<_> = chunks.into_iter()
+                    .map(|(chunk, line)| {
+                        scope.spawn(move || this.parse_chunk(chunk, line, mode))
+                    })
+                    .collect();
+                handles.into_iter()
+                    .map(|handle| handle.join().expect("parsing thread panicked"))
+                    .collect()
+            })
+        };
+
+        // In strict mode, report the first error in file order
+        for result in results {
+            let (table, diagnostics) = result?;
+            self.data.append(table);
+            self.diagnostics.append(diagnostics);
+        }
+        Ok(())
+    }
+
+    // Reads the data section in blocks of whole lines.
+    fn load_data<R: BufRead>(&mut self, mut reader: R, first_line: usize,
+                             mode: ParseMode)
+                             -> Result<(), ArffError> {
+        let mut line = first_line;
+        let mut block = Vec::new();
+        loop {
+            let io_error = |e, line| {
+                ArffError::new(ArffErrorKind::Io(e)).located(&self.filename, Some(line))
+            };
+            (&mut reader).take(BLOCK_SIZE)
+                .read_to_end(&mut block)
+                .and_then(|_| reader.read_until(b'\n', &mut block))
+                .map_err(|e| io_error(e, line))?;
+            if block.is_empty() {
+                return Ok(());
+            }
+
+            let text = match str::from_utf8(&block) {
+                Ok(text) => text,
+                Err(e) => {
+                    let bad = line + block[..e.valid_up_to()]
+                        .iter()
+                        .filter(|&&b| b == b'\n')
+                        .count();
+                    let e = io::Error::new(io::ErrorKind::InvalidData, e);
+                    return Err(io_error(e, bad));
+                }
+            };
+            self.load_block(text, line, mode)?;
+            line += text.matches('\n').count();
+            block.clear();
+        }
+    }
+
+    fn load_line(&mut self, line: &str) -> Result<bool, ArffError> {
+        let mut scanner = Scanner::new(line);
+        if scanner.at_end() {
+            return Ok(false);
+        }
+        let keyword = scanner.word().map_err(|e| ArffError::syntax(line, e))?;
+
+        match &*keyword.text.to_lowercase() {
+            "@relation" => {
+                let title = scanner.word().map_err(|_| {
+                    let what = "missing relation name";
+                    ArffError::new(ArffErrorKind::BadHeader(what))
+                })?;
+                self.title = title.text.into_owned();
+            }
+            "@attribute" => {
+                let name = scanner.word().map_err(|_| {
+                    let what = "expected attribute name and type";
+                    ArffError::new(ArffErrorKind::BadHeader(what))
+                })?;
+                let att_type = AttributeType::parse(line, &mut scanner)
+                    .map_err(|e| e.with_attribute(&name.text))?;
                 let attr = Attribute {
-                    name: name.to_string(),
-                    att_type: AttributeType::parse(t),
+                    name: name.text.into_owned(),
+                    att_type,
                 };
-                self.samples.push(AttributeSamples::from_attr(&attr));
-                self.attributes.push(attr);
+                if attr.att_type.relation().is_some() {
+                    // Wait for @end to declare it
+                    self.open_relations.push(attr);
+                } else {
+                    self.declare(attr);
+                }
+            }
+            "@end" => {
+                let name = scanner.word().map_err(|_| {
+                    let what = "expected the name of the relational attribute";
+                    ArffError::new(ArffErrorKind::BadHeader(what))
+                })?;
+                match self.open_relations.pop() {
+                    Some(attr) if attr.name == name.text => self.declare(attr),
+                    _ => {
+                        let what = "@end does not match an open relational attribute";
+                        return Err(ArffError::new(ArffErrorKind::BadHeader(what))
+                                       .with_token(line, &name));
+                    }
+                }
             }
-            Some("@data") => {
+            "@data" => {
+                if let Some(attr) = self.open_relations.last() {
+                    let what = "missing @end for relational attribute";
+                    return Err(ArffError::new(ArffErrorKind::BadHeader(what))
+                                   .with_attribute(&attr.name));
+                }
+                self.data = Table::new(&self.attributes, false);
                 // Consume the rest of the lines
-                return true;
+                return Ok(true);
             }
             _ => (),
         }
-        false
-    }
-
-    fn make_samples(&mut self) {
-        for (id, instance) in self.data.iter().enumerate() {
-            for (value, samples) in instance.values.iter().zip(self.samples.iter_mut()) {
-                match samples {
-                    &mut AttributeSamples::Numeric(ref mut list) => match value.num() {
-                        Some(f) => list.push((f, id)),
-                        None => (),
-                    },
-                    &mut AttributeSamples::Text(ref mut list) => match value.text() {
-                        Some(i) => list[i].0.push(id),
-                        None => (),
-                    },
-                    &mut AttributeSamples::BadType => (),
+        Ok(false)
+    }
+
+    // Adds a fully declared attribute to the innermost open relation, or to
+    // the dataset itself.
+    fn declare(&mut self, attr: Attribute) {
+        match self.open_relations.last_mut() {
+            Some(&mut Attribute {
+                att_type: AttributeType::Relational(ref mut attributes),
+                ..
+            }) => attributes.push(attr),
+            _ => self.attributes.push(attr),
+        }
+    }
+
+    fn make_samples(&self, att: usize) -> AttributeSamples {
+        let mut samples = AttributeSamples::from_attr(&self.attributes[att]);
+
+        for id in 0..self.data.len() {
+            let value = self.value(id, att);
+            match samples {
+                AttributeSamples::Numeric(ref mut list) => {
+                    let size = value.bag().map(|bag| bag.len() as f32);
+                    // NaN cannot be sorted, so it is left out like missing values
+                    if let Some(f) = value.num().or(size).filter(|f| !f.is_nan()) {
+                        list.push((f, id));
+                    }
+                }
+                AttributeSamples::Date(ref mut list) => {
+                    if let Some(d) = value.date() {
+                        list.push((d, id));
+                    }
                 }
+                AttributeSamples::Text(ref mut list) => {
+                    if let Some(i) = value.text() {
+                        list[i].0.push(id);
+                    }
+                }
+                AttributeSamples::BadType => (),
             }
         }
 
         // Now sort it
-        for samples in self.samples.iter_mut() {
-            match samples {
-                &mut AttributeSamples::Numeric(ref mut list) =>
-                    list.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal)),
-                _ => (),
+        match samples {
+            AttributeSamples::Numeric(ref mut list) => {
+                list.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
             }
+            AttributeSamples::Date(ref mut list) => list.sort(),
+            _ => (),
         }
+
+        samples
     }
 
     /// Loads a arff file
-    pub fn new(filename: &path::Path) -> ArffContent {
-        // Read the file line by line
-        let file = match fs::File::open(filename) {
-            Err(why) => panic!("Could not open file {}: {}", filename.display(), why),
-            Ok(file) => file,
-        };
+    pub fn new(filename: &path::Path, mode: ParseMode)
+               -> Result<ArffContent, ArffError> {
+        let name = filename.display().to_string();
+
+        let file = fs::File::open(filename).map_err(|e| {
+            ArffError::new(ArffErrorKind::Io(e)).located(&name, None)
+        })?;
 
+        Self::from_reader(file, &name, mode)
+    }
+
+    /// Loads arff content from any reader, such as stdin or a pipe.
+    ///
+    /// `name` is used in error messages and in the UI.
+    pub fn from_reader<R: Read>(reader: R, name: &str, mode: ParseMode)
+                                -> Result<ArffContent, ArffError> {
         let mut content = ArffContent {
-            filename: filename.to_str().unwrap().to_string(),
+            filename: name.to_string(),
             title: String::new(),
             attributes: Vec::new(),
-            data: Vec::new(),
+            data: Table::new(&[], false),
             samples: Vec::new(),
+            diagnostics: Diagnostics::new(),
+            class_attribute: None,
+            open_relations: Vec::new(),
         };
 
-        let reader = io::BufReader::new(file);
-        let mut reading_data = false;
+        // Compressed files are decompressed on the fly
+        let mut reader = decompress::decoder(io::BufReader::new(reader))
+            .map(io::BufReader::new)
+            .map_err(|e| {
+                ArffError::new(ArffErrorKind::Io(e)).located(&content.filename, None)
+            })?;
 
-        println!("Loading arff file...");
-        for raw_line in reader.lines() {
-            let line = raw_line.unwrap();
-            if line.starts_with("%") {
+        // The header is read line by line, until @data
+        let mut line = String::new();
+        let mut line_no = 0;
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line).map_err(|e| {
+                ArffError::new(ArffErrorKind::Io(e))
+                    .located(&content.filename, Some(line_no + 1))
+            })?;
+            if read == 0 {
+                break;
+            }
+            line_no += 1;
+            if line.starts_with('%') {
                 continue;
             }
 
+            let text = line.trim_end_matches('\n').trim_end_matches('\r');
+            let reading_data = content.load_line(text)
+                .map_err(|e| e.located(&content.filename, Some(line_no)))?;
             if reading_data {
-                // We are loading the data!
-                content.load_data_line(&line);
-            } else {
-                // We are still loading the header
-                if content.load_line(&line) {
-                    reading_data = true;
-                }
+                content.load_data(reader, line_no + 1, mode)?;
+                break;
             }
         }
 
-        content.make_samples();
+        content.samples = content.attributes
+            .iter()
+            .map(|_| OnceLock::new())
+            .collect();
+
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(text: &str) -> Result<ArffContent, ArffError> {
+        ArffContent::from_reader(text.as_bytes(), "test.arff", ParseMode::Strict)
+    }
+
+    #[test]
+    fn skips_indented_comment_rows() {
+        let content = load("@relation r\n@attribute a numeric\n@attribute b {x,y}\n@data\n\
+                            1,x\n   % note\n\t% other note\n2,y\n")
+            .unwrap();
+        assert_eq!(content.len(), 2);
+        assert_eq!(content.value(1, 0).num(), Some(2.0));
+    }
+
+    #[test]
+    fn leaves_nan_out_of_samples() {
+        let content = load("@relation r\n@attribute a numeric\n@data\n3\nNaN\n1\n?\n")
+            .unwrap();
+        assert!(content.value(1, 0).num().unwrap().is_nan());
+        match *content.samples(0) {
+            AttributeSamples::Numeric(ref samples) => assert_eq!(*samples, [(1.0, 2), (3.0, 0)]),
+            _ => panic!("numeric samples expected"),
+        }
+    }
+
+    #[test]
+    fn checks_the_class_attribute() {
+        let mut content = load("@relation r\n@attribute a numeric\n@attribute b {x}\n@data\n")
+            .unwrap();
+        assert_eq!(content.class_attribute(), None);
+        assert_eq!(content.default_class(), 1);
 
-        content
+        content.set_class_attribute(Some(0)).unwrap();
+        assert_eq!(content.class_attribute(), Some(0));
+        let error = content.set_class_attribute(Some(2)).err().unwrap();
+        assert!(matches!(error.kind, ArffErrorKind::BadClass));
+        assert_eq!(content.class_attribute(), Some(0));
+        content.set_class_attribute(None).unwrap();
+        assert_eq!(content.default_class(), 1);
     }
 }
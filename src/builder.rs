@@ -0,0 +1,282 @@
+// Building of datasets in memory, without going through a file: declare
+// the attributes, push rows of values, then finish.
+
+use arff::{ArffContent, ArffError, ArffErrorKind, Attribute, AttributeType, Value};
+use column::{Column, Table};
+
+/// Builds an `ArffContent` from values rather than from a file.
+///
+/// Rows are checked against the declared attributes as they are pushed.
+pub struct ArffBuilder {
+    title: String,
+    attributes: Vec<Attribute>,
+    class_attribute: Option<usize>,
+    // Created on the first row, once all attributes are known
+    data: Option<Table>,
+}
+
+// Name of a value's kind, for error messages.
+fn kind(value: &Value) -> &'static str {
+    match *value {
+        Value::Numeric(_) => "numeric",
+        Value::Date(_) => "date",
+        Value::Text(_) => "nominal",
+        Value::String(_) => "string",
+        Value::Relational(_) => "relational",
+        Value::Missing => "missing",
+    }
+}
+
+// Checks that `value` fits an attribute, down to the sub-instances of
+// relational values.
+fn check(attr: &Attribute, value: Value) -> Result<(), ArffError> {
+    let error = |kind| ArffError::new(kind).with_attribute(&attr.name);
+    match (&attr.att_type, value) {
+        (_, Value::Missing) |
+        (AttributeType::Numeric, Value::Numeric(_)) |
+        (AttributeType::Integer, Value::Numeric(_)) |
+        (AttributeType::Date(_), Value::Date(_)) |
+        (AttributeType::String, Value::String(_)) => Ok(()),
+        (AttributeType::Text(tokens), Value::Text(i)) => {
+            if i < tokens.len() {
+                Ok(())
+            } else {
+                let mut error = error(ArffErrorKind::UndeclaredToken);
+                error.token = Some(i.to_string());
+                Err(error)
+            }
+        }
+        (AttributeType::Relational(attributes), Value::Relational(bag)) => {
+            // The bag may come from a table with other attributes
+            let width = bag.table().width();
+            if width != attributes.len() {
+                return Err(error(ArffErrorKind::FieldCount {
+                    expected: attributes.len(),
+                    found: width,
+                }));
+            }
+            for i in 0..bag.len() {
+                for (att, nested) in attributes.iter().enumerate() {
+                    check(nested, bag.value(attributes, i, att)).map_err(|e| {
+                        let inner = e.located("", Some(i + 1));
+                        error(ArffErrorKind::BadBag(Box::new(inner)))
+                    })?;
+                }
+            }
+            Ok(())
+        }
+        (_, value) => {
+            let mut error = error(ArffErrorKind::WrongType);
+            error.token = Some(kind(&value).to_string());
+            Err(error)
+        }
+    }
+}
+
+// Stores a value that passed `check`.
+fn push(att_type: &AttributeType, value: Value, column: &mut Column, row: usize) {
+    match value {
+        Value::Numeric(f) => column.push_num(row, f),
+        Value::Date(d) => column.push_date(row, d),
+        Value::Text(i) => column.push_token(row, i),
+        Value::String(s) => column.push_str(row, s),
+        Value::Relational(bag) => {
+            // Copy the sub-instances, as they may come from another table
+            let attributes = att_type.relation().unwrap_or(&[]);
+            let mut nested = Table::new(attributes, false);
+            for i in 0..bag.len() {
+                for (att, attr) in attributes.iter().enumerate() {
+                    push(&attr.att_type, bag.value(attributes, i, att), nested.column_mut(att), i);
+                }
+                nested.end_row(1.0);
+            }
+            column.push_bag(row, nested);
+        }
+        Value::Missing => column.push_missing(row),
+    }
+}
+
+impl ArffBuilder {
+    /// Starts an empty dataset with the given relation name.
+    pub fn new(title: &str) -> Self {
+        ArffBuilder {
+            title: title.to_string(),
+            attributes: Vec::new(),
+            class_attribute: None,
+            data: None,
+        }
+    }
+
+    /// Declares the next attribute.
+    ///
+    /// Panics if rows were already pushed.
+    pub fn attribute(&mut self, name: &str, att_type: AttributeType) -> &mut Self {
+        assert!(self.data.is_none(), "attributes must be declared before any row");
+        self.attributes.push(Attribute {
+            name: name.to_string(),
+            att_type,
+        });
+        self
+    }
+
+    /// Declares the next attribute, and marks it as the class.
+    pub fn class(&mut self, name: &str, att_type: AttributeType) -> &mut Self {
+        self.class_attribute = Some(self.attributes.len());
+        self.attribute(name, att_type)
+    }
+
+    /// Attributes declared so far.
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    /// Adds an instance, with one value per attribute.
+    pub fn push(&mut self, values: &[Value]) -> Result<(), ArffError> {
+        self.push_weighted(values, 1.0)
+    }
+
+    /// Adds an instance with the given weight.
+    ///
+    /// Nothing is added if a value does not match its attribute.
+    pub fn push_weighted(&mut self, values: &[Value], weight: f32) -> Result<(), ArffError> {
+        if values.len() != self.attributes.len() {
+            let kind = ArffErrorKind::FieldCount {
+                expected: self.attributes.len(),
+                found: values.len(),
+            };
+            return Err(ArffError::new(kind).located(&self.title, None));
+        }
+        if !(weight >= 0.0 && weight.is_finite()) {
+            let mut error = ArffError::new(ArffErrorKind::BadWeight);
+            error.token = Some(weight.to_string());
+            return Err(error.located(&self.title, None));
+        }
+        for (attr, &value) in self.attributes.iter().zip(values) {
+            check(attr, value).map_err(|e| e.located(&self.title, None))?;
+        }
+
+        let attributes = &self.attributes;
+        let data = self.data.get_or_insert_with(|| Table::new(attributes, false));
+        let row = data.len();
+        for (att, (attr, &value)) in attributes.iter().zip(values).enumerate() {
+            push(&attr.att_type, value, data.column_mut(att), row);
+        }
+        data.end_row(weight);
+        Ok(())
+    }
+
+    /// Number of instances pushed so far.
+    pub fn len(&self) -> usize {
+        self.data.as_ref().map_or(0, Table::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the dataset. Per-attribute samples are computed when first
+    /// needed, as for loaded files.
+    pub fn finish(self) -> ArffContent {
+        let data = match self.data {
+            Some(data) => data,
+            None => Table::new(&self.attributes, false),
+        };
+        let mut content = ArffContent::from_table(&self.title, &self.title, self.attributes, data);
+        // Declared with the attributes, so it exists
+        content.set_class_attribute(self.class_attribute).unwrap();
+        content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arff::ParseMode;
+
+    fn load(text: &str) -> ArffContent {
+        ArffContent::from_reader(text.as_bytes(), "test.arff", ParseMode::Strict).ok().unwrap()
+    }
+
+    fn points() -> AttributeType {
+        AttributeType::Relational(vec![Attribute {
+                                           name: "x".to_string(),
+                                           att_type: AttributeType::Numeric,
+                                       },
+                                       Attribute {
+                                           name: "y".to_string(),
+                                           att_type: AttributeType::Numeric,
+                                       }])
+    }
+
+    #[test]
+    fn rejects_mismatched_values() {
+        let mut builder = ArffBuilder::new("test");
+        builder.attribute("x", AttributeType::Numeric)
+            .attribute("s", AttributeType::String);
+        let error = builder.push(&[Value::String("a"), Value::String("b")]).err().unwrap();
+        assert_eq!(error.attribute.as_deref(), Some("x"));
+        assert!(matches!(error.kind, ArffErrorKind::WrongType));
+        let error = builder.push(&[Value::Numeric(1.0)]).err().unwrap();
+        assert!(matches!(error.kind, ArffErrorKind::FieldCount { expected: 2, found: 1 }));
+        assert!(builder.is_empty());
+    }
+
+    #[test]
+    fn keeps_weights() {
+        let mut builder = ArffBuilder::new("test");
+        builder.attribute("x", AttributeType::Numeric);
+        builder.push(&[Value::Numeric(1.0)]).unwrap();
+        builder.push_weighted(&[Value::Numeric(2.0)], 0.5).unwrap();
+        assert!(builder.push_weighted(&[Value::Numeric(3.0)], -1.0).is_err());
+        let content = builder.finish();
+        let weights: Vec<_> = content.instances().map(|instance| instance.weight()).collect();
+        assert_eq!(weights, [1.0, 0.5]);
+    }
+
+    #[test]
+    fn copies_bags() {
+        let source = load("@relation r\n\
+                           @attribute points relational\n\
+                           @attribute x numeric\n\
+                           @attribute y numeric\n\
+                           @end points\n\
+                           @data\n\
+                           '1,2\\n3,?'\n");
+        let mut builder = ArffBuilder::new("test");
+        builder.attribute("points", points());
+        builder.push(&[source.value(0, 0)]).unwrap();
+        builder.push(&[Value::Missing]).unwrap();
+        let content = builder.finish();
+        drop(source);
+        assert_eq!(content.describe_sample(0), "'1,2\\n3,?'");
+        assert_eq!(content.describe_sample(1), "?");
+    }
+
+    #[test]
+    fn rejects_bags_of_other_relations() {
+        let source = load("@relation r\n\
+                           @attribute narrow relational\n\
+                           @attribute x numeric\n\
+                           @end narrow\n\
+                           @attribute words relational\n\
+                           @attribute x string\n\
+                           @attribute y numeric\n\
+                           @end words\n\
+                           @data\n\
+                           '1','a,2'\n");
+        let mut builder = ArffBuilder::new("test");
+        builder.attribute("points", points());
+
+        let error = builder.push(&[source.value(0, 0)]).err().unwrap();
+        assert!(matches!(error.kind, ArffErrorKind::FieldCount { expected: 2, found: 1 }));
+        let error = builder.push(&[source.value(0, 1)]).err().unwrap();
+        match error.kind {
+            ArffErrorKind::BadBag(ref inner) => {
+                assert!(matches!(inner.kind, ArffErrorKind::WrongType));
+                assert_eq!(inner.line, Some(1));
+            }
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+        assert!(builder.is_empty());
+    }
+}
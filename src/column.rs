@@ -0,0 +1,451 @@
+// Columnar storage for the instances of a dataset: one typed vector per
+// attribute rather than one vector of values per instance, so that wide
+// datasets stay compact in memory.
+
+use arff::{Attribute, AttributeType, Value};
+use std::collections::HashMap;
+use std::ops;
+use std::sync::Arc;
+
+// Code of missing nominal and string values
+const MISSING: u32 = u32::MAX;
+
+/// One bit per row, set when the row holds a value.
+#[derive(Default)]
+struct Bitmap {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitmap {
+    pub(crate) fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if bit {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn get(&self, i: usize) -> bool {
+        (self.words[i / 64] >> (i % 64)) & 1 == 1
+    }
+
+    fn append(&mut self, other: &Bitmap) {
+        for i in 0..other.len {
+            self.push(other.get(i));
+        }
+    }
+}
+
+/// Distinct strings of a column, each stored once.
+#[derive(Default)]
+struct Interner {
+    strings: Vec<Arc<str>>,
+    codes: HashMap<Arc<str>, u32>,
+}
+
+impl Interner {
+    /// Returns the code of `s`, adding it if needed.
+    pub(crate) fn intern(&mut self, s: &str) -> u32 {
+        if let Some(&code) = self.codes.get(s) {
+            return code;
+        }
+        let s: Arc<str> = Arc::from(s);
+        let code = self.strings.len() as u32;
+        self.strings.push(s.clone());
+        self.codes.insert(s, code);
+        code
+    }
+
+    pub(crate) fn get(&self, code: u32) -> &str {
+        &self.strings[code as usize]
+    }
+}
+
+/// Values of a single attribute.
+pub(crate) struct Column {
+    // In sparse tables, the rows holding an explicit value, in order. The
+    // other rows take the sparse default of the attribute.
+    rows: Option<Vec<u32>>,
+    data: ColumnData,
+}
+
+enum ColumnData {
+    Numeric(Vec<f32>, Bitmap),
+    // Milliseconds since the epoch
+    Date(Vec<i64>, Bitmap),
+    // Index of the declared token
+    Nominal(Vec<u32>),
+    String(Vec<u32>, Interner),
+    // Sub-instances of all bags, one after the other: bag `i` spans
+    // `offsets[i]..offsets[i + 1]` in the nested table.
+    Relational(Box<Table>, Vec<u32>, Bitmap),
+}
+
+impl Column {
+    pub(crate) fn new(att_type: &AttributeType, sparse: bool) -> Self {
+        let data = match *att_type {
+            AttributeType::Numeric | AttributeType::Integer => {
+                ColumnData::Numeric(Vec::new(), Bitmap::default())
+            }
+            AttributeType::Date(_) => {
+                ColumnData::Date(Vec::new(), Bitmap::default())
+            }
+            AttributeType::Text(_) => ColumnData::Nominal(Vec::new()),
+            AttributeType::String => {
+                ColumnData::String(Vec::new(), Interner::default())
+            }
+            AttributeType::Relational(ref attributes) => {
+                ColumnData::Relational(Box::new(Table::new(attributes, false)),
+                                       vec![0],
+                                       Bitmap::default())
+            }
+        };
+        Column {
+            rows: if sparse { Some(Vec::new()) } else { None },
+            data,
+        }
+    }
+
+    // Records that `row` has an explicit value.
+    fn mark(&mut self, row: usize) {
+        if let Some(ref mut rows) = self.rows {
+            rows.push(row as u32);
+        }
+    }
+
+    pub(crate) fn push_num(&mut self, row: usize, f: f32) {
+        self.mark(row);
+        match self.data {
+            ColumnData::Numeric(ref mut values, ref mut valid) => {
+                values.push(f);
+                valid.push(true);
+            }
+            _ => panic!("not a numeric column"),
+        }
+    }
+
+    pub(crate) fn push_date(&mut self, row: usize, date: i64) {
+        self.mark(row);
+        match self.data {
+            ColumnData::Date(ref mut values, ref mut valid) => {
+                values.push(date);
+                valid.push(true);
+            }
+            _ => panic!("not a date column"),
+        }
+    }
+
+    pub(crate) fn push_token(&mut self, row: usize, index: usize) {
+        self.mark(row);
+        match self.data {
+            ColumnData::Nominal(ref mut codes) => codes.push(index as u32),
+            _ => panic!("not a nominal column"),
+        }
+    }
+
+    pub(crate) fn push_str(&mut self, row: usize, s: &str) {
+        self.mark(row);
+        match self.data {
+            ColumnData::String(ref mut codes, ref mut strings) => {
+                codes.push(strings.intern(s))
+            }
+            _ => panic!("not a string column"),
+        }
+    }
+
+    /// Adds a bag of sub-instances, read with the nested attributes.
+    pub(crate) fn push_bag(&mut self, row: usize, bag: Table) {
+        self.mark(row);
+        match self.data {
+            ColumnData::Relational(ref mut nested, ref mut offsets, ref mut valid) => {
+                nested.append(bag);
+                offsets.push(nested.len() as u32);
+                valid.push(true);
+            }
+            _ => panic!("not a relational column"),
+        }
+    }
+
+    pub(crate) fn push_missing(&mut self, row: usize) {
+        self.mark(row);
+        match self.data {
+            ColumnData::Numeric(ref mut values, ref mut valid) => {
+                values.push(0.0);
+                valid.push(false);
+            }
+            ColumnData::Date(ref mut values, ref mut valid) => {
+                values.push(0);
+                valid.push(false);
+            }
+            ColumnData::Nominal(ref mut codes) |
+            ColumnData::String(ref mut codes, _) => codes.push(MISSING),
+            ColumnData::Relational(ref nested, ref mut offsets, ref mut valid) => {
+                offsets.push(nested.len() as u32);
+                valid.push(false);
+            }
+        }
+    }
+
+    /// Returns the value of the given row.
+    ///
+    /// Returns `None` if the row was left out of a sparse table: see
+    /// `AttributeType::sparse_default` for the value it then takes.
+    pub(crate) fn get(&self, row: usize) -> Option<Value<'_>> {
+        let i = match self.rows {
+            Some(ref rows) => rows.binary_search(&(row as u32)).ok()?,
+            None => row,
+        };
+        Some(match self.data {
+            ColumnData::Numeric(ref values, ref valid) => {
+                if valid.get(i) {
+                    Value::Numeric(values[i])
+                } else {
+                    Value::Missing
+                }
+            }
+            ColumnData::Date(ref values, ref valid) => {
+                if valid.get(i) {
+                    Value::Date(values[i])
+                } else {
+                    Value::Missing
+                }
+            }
+            ColumnData::Nominal(ref codes) => match codes[i] {
+                MISSING => Value::Missing,
+                code => Value::Text(code as usize),
+            },
+            ColumnData::String(ref codes, ref strings) => match codes[i] {
+                MISSING => Value::Missing,
+                code => Value::String(strings.get(code)),
+            },
+            ColumnData::Relational(ref nested, ref offsets, ref valid) => {
+                if valid.get(i) {
+                    Value::Relational(Bag {
+                        table: nested,
+                        start: offsets[i] as usize,
+                        end: offsets[i + 1] as usize,
+                    })
+                } else {
+                    Value::Missing
+                }
+            }
+        })
+    }
+
+    // Adds the values of `other`, whose rows start at `offset`.
+    fn append(&mut self, other: Column, offset: usize) {
+        if let (Some(rows), Some(other_rows)) = (self.rows.as_mut(), other.rows) {
+            rows.extend(other_rows.into_iter().map(|row| row + offset as u32));
+        }
+
+        match (&mut self.data, other.data) {
+            (&mut ColumnData::Numeric(ref mut values, ref mut valid),
+             ColumnData::Numeric(other_values, other_valid)) => {
+                values.extend(other_values);
+                valid.append(&other_valid);
+            }
+            (&mut ColumnData::Date(ref mut values, ref mut valid),
+             ColumnData::Date(other_values, other_valid)) => {
+                values.extend(other_values);
+                valid.append(&other_valid);
+            }
+            (&mut ColumnData::Nominal(ref mut codes), ColumnData::Nominal(other_codes)) => {
+                codes.extend(other_codes);
+            }
+            (&mut ColumnData::String(ref mut codes, ref mut strings),
+             ColumnData::String(other_codes, other_strings)) => {
+                // Codes are only meaningful within a column
+                codes.extend(other_codes.into_iter().map(|code| match code {
+                    MISSING => MISSING,
+                    code => strings.intern(other_strings.get(code)),
+                }));
+            }
+            (&mut ColumnData::Relational(ref mut nested, ref mut offsets, ref mut valid),
+             ColumnData::Relational(other_nested, other_offsets, other_valid)) => {
+                let shift = nested.len() as u32;
+                offsets.extend(other_offsets[1..].iter().map(|&o| o + shift));
+                valid.append(&other_valid);
+                nested.append(*other_nested);
+            }
+            _ => panic!("appending columns of different types"),
+        }
+    }
+}
+
+/// Sub-instances of a relational value.
+#[derive(Clone, Copy)]
+pub struct Bag<'a> {
+    table: &'a Table,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Bag<'a> {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The table holding the sub-instances, shared with the other bags.
+    pub(crate) fn table(&self) -> &'a Table {
+        self.table
+    }
+
+    /// The rows of `table()` that belong to this bag.
+    pub(crate) fn rows(&self) -> ops::Range<usize> {
+        self.start..self.end
+    }
+
+    /// Returns a value of the `i`-th sub-instance, given the nested
+    /// attributes.
+    pub fn value(&self, attributes: &[Attribute], i: usize, att: usize)
+                 -> Value<'a> {
+        self.table.value(attributes, self.start + i, att)
+    }
+
+    /// Writes the `i`-th sub-instance as it would appear in an arff file.
+    pub fn describe(&self, attributes: &[Attribute], i: usize) -> String {
+        self.table.describe(attributes, self.start + i)
+    }
+}
+
+/// The instances of a dataset, stored by attribute.
+pub(crate) struct Table {
+    columns: Vec<Column>,
+    // Only allocated once an instance has a weight other than 1
+    weights: Option<Vec<f32>>,
+    len: usize,
+    sparse: bool,
+}
+
+impl Table {
+    /// Creates an empty table for the given attributes.
+    ///
+    /// Sparse tables only store the values given explicitly.
+    pub(crate) fn new(attributes: &[Attribute], sparse: bool) -> Self {
+        Table {
+            columns: attributes.iter()
+                .map(|attr| Column::new(&attr.att_type, sparse))
+                .collect(),
+            weights: None,
+            len: 0,
+            sparse,
+        }
+    }
+
+    /// Number of instances.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of attributes.
+    pub(crate) fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub(crate) fn is_sparse(&self) -> bool {
+        self.sparse
+    }
+
+    /// Values of the given attribute.
+    pub(crate) fn column(&self, att: usize) -> &Column {
+        &self.columns[att]
+    }
+
+    /// Column being filled for the row at index `len()`.
+    pub(crate) fn column_mut(&mut self, att: usize) -> &mut Column {
+        &mut self.columns[att]
+    }
+
+    /// Completes the current row, once all its values were pushed.
+    pub(crate) fn end_row(&mut self, weight: f32) {
+        if weight != 1.0 && self.weights.is_none() {
+            self.weights = Some(vec![1.0; self.len]);
+        }
+        if let Some(ref mut weights) = self.weights {
+            weights.push(weight);
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn weight(&self, row: usize) -> f32 {
+        match self.weights {
+            Some(ref weights) => weights[row],
+            None => 1.0,
+        }
+    }
+
+    /// Returns `true` if some instances were given a weight other than 1.
+    pub(crate) fn has_weights(&self) -> bool {
+        self.weights.is_some()
+    }
+
+    /// Returns the value of an attribute, sparse default included.
+    pub(crate) fn value(&self, attributes: &[Attribute], row: usize, att: usize)
+                 -> Value<'_> {
+        match self.columns[att].get(row) {
+            Some(value) => value,
+            None => attributes[att].att_type.sparse_default(),
+        }
+    }
+
+    /// Writes an instance as it would appear in an arff file.
+    pub(crate) fn describe(&self, attributes: &[Attribute], row: usize) -> String {
+        let mut line = if self.sparse {
+            let pairs: Vec<_> = attributes.iter()
+                .enumerate()
+                .filter_map(|(att, attr)| {
+                    self.columns[att].get(row).map(|value| {
+                        format!("{} {}", att, attr.att_type.describe(value))
+                    })
+                })
+                .collect();
+            format!("{{{}}}", pairs.join(","))
+        } else {
+            attributes.iter()
+                .enumerate()
+                .map(|(att, attr)| attr.att_type.describe(self.value(attributes, row, att)))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        let weight = self.weight(row);
+        if weight != 1.0 {
+            line.push_str(&format!(",{{{}}}", weight));
+        }
+        line
+    }
+
+    /// Adds the instances of `other`, which must have the same attributes
+    /// and sparseness.
+    pub(crate) fn append(&mut self, other: Table) {
+        assert_eq!(self.sparse, other.sparse);
+
+        match (self.weights.as_mut(), other.weights) {
+            (Some(weights), Some(other_weights)) => weights.extend(other_weights),
+            (Some(weights), None) => weights.extend(vec![1.0; other.len]),
+            (None, Some(other_weights)) => {
+                let mut weights = vec![1.0; self.len];
+                weights.extend(other_weights);
+                self.weights = Some(weights);
+            }
+            (None, None) => (),
+        }
+
+        let offset = self.len;
+        for (column, other_column) in self.columns.iter_mut().zip(other.columns) {
+            column.append(other_column, offset);
+        }
+        self.len += other.len;
+    }
+}
@@ -0,0 +1,230 @@
+// Conversion of a loaded dataset to other formats, Apache Arrow and
+// Parquet in particular, so it can be read quickly elsewhere.
+
+use arff::{self, ArffContent, Attribute, AttributeType, Value};
+use arrow_array::types::Int32Type;
+use arrow_array::{ArrayRef, DictionaryArray, Float32Array, Int32Array, ListArray, RecordBatch,
+                  StringArray, StructArray, TimestampMillisecondArray};
+use arrow_buffer::{BooleanBufferBuilder, OffsetBuffer};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Fields, Schema, TimeUnit};
+use column::Table;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::ops;
+use std::path;
+use std::sync::Arc;
+use writer;
+use xrff;
+
+// Arrow type of the values of an attribute.
+fn data_type(att_type: &AttributeType) -> DataType {
+    match *att_type {
+        AttributeType::Numeric | AttributeType::Integer => DataType::Float32,
+        AttributeType::Date(_) => DataType::Timestamp(TimeUnit::Millisecond, None),
+        AttributeType::Text(_) => {
+            DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+        }
+        AttributeType::String => DataType::Utf8,
+        // A list of sub-instances
+        AttributeType::Relational(ref attributes) => {
+            let item = Field::new("item", DataType::Struct(fields(attributes)), true);
+            DataType::List(Arc::new(item))
+        }
+    }
+}
+
+fn fields(attributes: &[Attribute]) -> Fields {
+    attributes.iter()
+        .map(|attr| Field::new(attr.name.clone(), data_type(&attr.att_type), true))
+        .collect()
+}
+
+// Builds the array of an attribute for the given rows of a table.
+fn array(table: &Table, attributes: &[Attribute], att: usize, rows: &[usize]) -> ArrayRef {
+    let values = rows.iter().map(|&row| table.value(attributes, row, att));
+    match attributes[att].att_type {
+        AttributeType::Numeric | AttributeType::Integer => {
+            Arc::new(values.map(|value| value.num()).collect::<Float32Array>())
+        }
+        AttributeType::Date(_) => {
+            Arc::new(values.map(|value| value.date()).collect::<TimestampMillisecondArray>())
+        }
+        AttributeType::String => {
+            Arc::new(values.map(|value| value.string()).collect::<StringArray>())
+        }
+        AttributeType::Text(ref tokens) => {
+            // Keys refer to the declared tokens, in order
+            let keys = values.map(|value| value.text().map(|i| i as i32))
+                .collect::<Int32Array>();
+            let tokens = Arc::new(StringArray::from_iter_values(tokens));
+            Arc::new(DictionaryArray::<Int32Type>::new(keys, tokens))
+        }
+        AttributeType::Relational(ref nested) => {
+            // Bags all live in the same nested table
+            let empty = Table::new(nested, false);
+            let mut bags = &empty;
+            let mut sub_rows = Vec::new();
+            let mut offsets = vec![0];
+            let mut valid = BooleanBufferBuilder::new(rows.len());
+            for value in values {
+                match value {
+                    Value::Relational(bag) => {
+                        bags = bag.table();
+                        sub_rows.extend(bag.rows());
+                        valid.append(true);
+                    }
+                    _ => valid.append(false),
+                }
+                offsets.push(sub_rows.len() as i32);
+            }
+
+            let columns = (0..nested.len())
+                .map(|att| array(bags, nested, att, &sub_rows))
+                .collect();
+            let items = StructArray::new(fields(nested), columns, None);
+            let item = match data_type(&attributes[att].att_type) {
+                DataType::List(item) => item,
+                _ => unreachable!(),
+            };
+            Arc::new(ListArray::new(item,
+                                    OffsetBuffer::new(offsets.into()),
+                                    Arc::new(items),
+                                    Some(valid.finish().into())))
+        }
+    }
+}
+
+// Rows written per record batch, so that large datasets are not converted
+// all at once.
+const BATCH_ROWS: usize = 64 * 1024;
+
+/// Schema of the record batches of `content`: a column per attribute, and
+/// the instance weights, if any, last.
+///
+/// The weight column is named `weight`, or `weight_`, `weight__`... if an
+/// attribute is already named so.
+pub fn schema(content: &ArffContent) -> Schema {
+    let mut fields: Vec<Field> = fields(content.attributes())
+        .iter()
+        .map(|field| (**field).clone())
+        .collect();
+    if content.has_weights() {
+        fields.push(Field::new(arff::unique_name(content.attributes(), "weight"), DataType::Float32, false));
+    }
+    Schema::new(fields)
+}
+
+/// Builds a record batch holding the given instances of `content`, as
+/// described by `schema`.
+pub fn to_record_batch(content: &ArffContent, schema: &Arc<Schema>, rows: ops::Range<usize>)
+                       -> Result<RecordBatch, ArrowError> {
+    let rows: Vec<usize> = rows.collect();
+    let mut columns: Vec<ArrayRef> = (0..content.attributes().len())
+        .map(|att| array(&content.data, content.attributes(), att, &rows))
+        .collect();
+    if content.has_weights() {
+        let weights = rows.iter().map(|&row| content.data.weight(row));
+        columns.push(Arc::new(Float32Array::from_iter_values(weights)));
+    }
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+// Calls `write` with successive batches of at most `BATCH_ROWS` instances.
+fn write_batches<F>(content: &ArffContent, schema: &Arc<Schema>, mut write: F)
+                    -> Result<(), ArrowError>
+    where F: FnMut(&RecordBatch) -> Result<(), ArrowError>
+{
+    for start in (0..content.len()).step_by(BATCH_ROWS) {
+        let end = (start + BATCH_ROWS).min(content.len());
+        write(&to_record_batch(content, schema, start..end)?)?;
+    }
+    Ok(())
+}
+
+/// Writes `content` as an Arrow IPC file.
+pub fn write_arrow(file: File, content: &ArffContent) -> Result<(), ArrowError> {
+    let schema = Arc::new(schema(content));
+    let mut writer = FileWriter::try_new(BufWriter::new(file), &schema)?;
+    write_batches(content, &schema, |batch| writer.write(batch))?;
+    writer.finish()
+}
+
+/// Writes `content` as a snappy-compressed Parquet file.
+pub fn write_parquet(file: File, content: &ArffContent) -> Result<(), ArrowError> {
+    let schema = Arc::new(schema(content));
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))?;
+    write_batches(content, &schema, |batch| Ok(writer.write(batch)?))?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Writes all of `content` to `output`, in the format given by its
+/// extension: `.parquet`, `.arrow` (or `.ipc`, `.feather`), `.arff` or
+/// `.xrff`.
+pub fn convert(content: &ArffContent, output: &path::Path) -> Result<(), String> {
+    let format = output.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    if !matches!(format, "parquet" | "arrow" | "ipc" | "feather" | "arff" | "xrff") {
+        return Err(format!("{}: unknown output format `{}`", output.display(), format));
+    }
+    let file = File::create(output)
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+    let all = 0..content.data.len();
+    let written = match format {
+        "parquet" => write_parquet(file, content).map_err(|e| e.to_string()),
+        "arrow" | "ipc" | "feather" => write_arrow(file, content).map_err(|e| e.to_string()),
+        "arff" => {
+            writer::write_arff(&mut BufWriter::new(file), content, all)
+                .map_err(|e: io::Error| e.to_string())
+        }
+        "xrff" => {
+            xrff::write_xrff(&mut BufWriter::new(file), content, all)
+                .map_err(|e: io::Error| e.to_string())
+        }
+        _ => unreachable!(),
+    };
+    written.map_err(|e| format!("{}: {}", output.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arff::AttributeType;
+    use builder::ArffBuilder;
+
+    fn dataset(rows: usize) -> ArffContent {
+        let mut builder = ArffBuilder::new("test");
+        builder.attribute("weight", AttributeType::Numeric)
+            .attribute("weight_", AttributeType::Numeric);
+        for row in 0..rows {
+            let x = row as f32;
+            builder.push_weighted(&[Value::Numeric(x), Value::Numeric(-x)], 2.0).unwrap();
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn weight_column_does_not_clash() {
+        let schema = schema(&dataset(1));
+        let names: Vec<_> = schema.fields().iter().map(|field| field.name().as_str()).collect();
+        assert_eq!(names, ["weight", "weight_", "weight__"]);
+    }
+
+    #[test]
+    fn writes_in_batches() {
+        let content = dataset(BATCH_ROWS + 10);
+        let schema = Arc::new(schema(&content));
+        let mut lengths = Vec::new();
+        write_batches(&content, &schema, |batch| {
+            lengths.push(batch.num_rows());
+            Ok(())
+        }).unwrap();
+        assert_eq!(lengths, [BATCH_ROWS, 10]);
+    }
+}
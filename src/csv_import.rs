@@ -0,0 +1,258 @@
+// Loading of CSV and TSV files with a header row. Column types are not
+// declared, so they are inferred from the values.
+
+use arff::{self, ArffContent, ArffError, ArffErrorKind, Attribute, AttributeType,
+           Diagnostic, Diagnostics, ParseMode};
+use column::Table;
+use csv;
+use decompress;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path;
+use std::slice;
+use tokenizer::Token;
+
+// Above this many distinct values, text columns are read as strings
+const MAX_NOMINAL: usize = 64;
+
+/// How to read a delimited file.
+pub struct CsvOptions {
+    pub delimiter: u8,
+    /// Columns to read as nominal, whatever their values.
+    pub nominal: Vec<String>,
+    /// Columns to read as strings, whatever their values.
+    pub string: Vec<String>,
+    /// In lenient mode, rows with the wrong number of cells are skipped
+    /// instead of failing.
+    pub mode: ParseMode,
+}
+
+impl CsvOptions {
+    /// Default options for the given file: tab-separated for `.tsv` and
+    /// `.tab` files, comma-separated otherwise.
+    pub fn for_path(filename: &path::Path) -> Self {
+        let delimiter = match decompress::format_of(filename) {
+            Some("tsv") | Some("tab") => b'\t',
+            _ => b',',
+        };
+        CsvOptions {
+            delimiter,
+            nominal: Vec::new(),
+            string: Vec::new(),
+            mode: ParseMode::Strict,
+        }
+    }
+}
+
+/// Returns `true` if the file should be read as CSV or TSV.
+pub fn is_delimited(filename: &path::Path) -> bool {
+    matches!(decompress::format_of(filename), Some("csv") | Some("tsv") | Some("tab"))
+}
+
+// `?` and empty cells stand for missing values.
+fn is_missing(cell: &str) -> bool {
+    cell.is_empty() || cell == "?"
+}
+
+// Picks a type for a column from its values.
+fn infer(records: &[csv::StringRecord], col: usize) -> AttributeType {
+    let cells = || {
+        records.iter()
+            .map(move |record| &record[col])
+            .filter(|cell| !is_missing(cell))
+    };
+
+    let numbers: Option<Vec<f32>> = cells()
+        .map(|cell| arff::parse_f32(cell).ok())
+        .collect();
+    if let Some(numbers) = numbers {
+        if !numbers.is_empty() && numbers.iter().all(|f| f.fract() == 0.0) {
+            return AttributeType::Integer;
+        }
+        return AttributeType::Numeric;
+    }
+
+    // Low cardinality text, with values repeated on average
+    let tokens = distinct(records, col);
+    if tokens.len() <= MAX_NOMINAL && tokens.len() * 2 <= cells().count() {
+        AttributeType::Text(tokens)
+    } else {
+        AttributeType::String
+    }
+}
+
+// Distinct values of a column, in order of appearance.
+fn distinct(records: &[csv::StringRecord], col: usize) -> Vec<String> {
+    let mut seen = HashSet::new();
+    records.iter()
+        .map(|record| &record[col])
+        .filter(|cell| !is_missing(cell) && seen.insert(*cell))
+        .map(|cell| cell.to_string())
+        .collect()
+}
+
+// Returns `true` if `column` refers to the given header, by name or index.
+fn selects(columns: &[String], name: &str, col: usize) -> bool {
+    columns.iter().any(|column| *column == name || *column == col.to_string())
+}
+
+// Fails on the first column that matches no header.
+fn check_columns(columns: &[String], headers: &csv::StringRecord)
+                 -> Result<(), ArffError> {
+    for column in columns {
+        let column = slice::from_ref(column);
+        if !headers.iter().enumerate().any(|(col, name)| selects(column, name, col)) {
+            let mut error = ArffError::new(ArffErrorKind::UnknownColumn);
+            error.token = Some(column[0].clone());
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Loads a CSV or TSV file.
+pub fn load(filename: &path::Path, options: &CsvOptions)
+            -> Result<ArffContent, ArffError> {
+    let name = filename.display().to_string();
+    let file = fs::File::open(filename).map_err(|e| {
+        ArffError::new(ArffErrorKind::Io(e)).located(&name, None)
+    })?;
+    let title = filename.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| name.clone());
+    from_reader(file, &name, &title, options)
+}
+
+/// Loads CSV or TSV content from any reader.
+pub fn from_reader<R: Read>(reader: R, name: &str, title: &str,
+                            options: &CsvOptions)
+                            -> Result<ArffContent, ArffError> {
+    let csv_error = |e: csv::Error| {
+        let line = e.position().map(|p| p.line() as usize);
+        ArffError::new(ArffErrorKind::Io(io::Error::from(e))).located(name, line)
+    };
+
+    let reader = decompress::decoder(io::BufReader::new(reader))
+        .map_err(|e| ArffError::new(ArffErrorKind::Io(e)).located(name, None))?;
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .flexible(true)
+        .from_reader(reader);
+
+    let headers = reader.headers().map_err(csv_error)?.clone();
+    check_columns(&options.nominal, &headers)
+        .and_then(|_| check_columns(&options.string, &headers))
+        .map_err(|e| e.located(name, Some(1)))?;
+
+    let mut records = Vec::new();
+    let mut diagnostics = Diagnostics::new();
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        if record.len() == headers.len() {
+            records.push(record);
+            continue;
+        }
+        let line = record.position().map(|p| p.line() as usize);
+        let error = ArffError::new(ArffErrorKind::FieldCount {
+                expected: headers.len(),
+                found: record.len(),
+            })
+            .located(name, line);
+        // Skip the whole row
+        let mut problems = Vec::new();
+        arff::report(options.mode, &mut problems, error)?;
+        for error in problems {
+            let delimiter = (options.delimiter as char).to_string();
+            let text = record.iter().collect::<Vec<_>>().join(&delimiter);
+            diagnostics.push(Diagnostic { error, text });
+        }
+    }
+
+    let attributes: Vec<Attribute> = headers.iter()
+        .enumerate()
+        .map(|(col, header)| {
+            let att_type = if selects(&options.string, header, col) {
+                AttributeType::String
+            } else if selects(&options.nominal, header, col) {
+                AttributeType::Text(distinct(&records, col))
+            } else {
+                infer(&records, col)
+            };
+            Attribute {
+                name: header.to_string(),
+                att_type,
+            }
+        })
+        .collect();
+
+    let mut data = Table::new(&attributes, false);
+    for record in &records {
+        let row = data.len();
+        for (col, (cell, attr)) in record.iter().zip(attributes.iter()).enumerate() {
+            let column = data.column_mut(col);
+            if is_missing(cell) {
+                column.push_missing(row);
+                continue;
+            }
+            // Inferred types accept all the values of their column
+            let token = Token {
+                offset: 0,
+                text: Cow::Borrowed(cell),
+                quoted: true,
+            };
+            if attr.att_type.parse_value(&token, column, row).is_err() {
+                column.push_missing(row);
+            }
+        }
+        data.end_row(1.0);
+    }
+
+    let mut content = ArffContent::from_table(name, title, attributes, data);
+    content.set_diagnostics(diagnostics);
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(mode: ParseMode) -> CsvOptions {
+        let mut options = CsvOptions::for_path(path::Path::new("test.csv"));
+        options.mode = mode;
+        options
+    }
+
+    fn load(text: &str, options: &CsvOptions) -> Result<ArffContent, ArffError> {
+        from_reader(text.as_bytes(), "test.csv", "test", options)
+    }
+
+    #[test]
+    fn rejects_unknown_columns() {
+        let mut options = options(ParseMode::Strict);
+        options.nominal = vec!["b".to_string(), "0".to_string()];
+        assert!(load("a,b\n1,x\n", &options).is_ok());
+
+        options.string = vec!["c".to_string()];
+        let error = load("a,b\n1,x\n", &options).err().unwrap();
+        assert!(matches!(error.kind, ArffErrorKind::UnknownColumn));
+        assert_eq!(error.token.as_deref(), Some("c"));
+
+        options.string = vec!["2".to_string()];
+        assert!(load("a,b\n1,x\n", &options).is_err());
+    }
+
+    #[test]
+    fn lenient_skips_ragged_rows() {
+        let text = "a,b\n1,x\n2\n3,y,z\n4,w\n";
+        let error = load(text, &options(ParseMode::Strict)).err().unwrap();
+        assert!(matches!(error.kind,
+                         ArffErrorKind::FieldCount { expected: 2, found: 1 }));
+        assert_eq!(error.line, Some(3));
+
+        let content = load(text, &options(ParseMode::Lenient)).unwrap();
+        assert_eq!(content.len(), 2);
+        assert_eq!(content.diagnostics().len(), 2);
+    }
+}
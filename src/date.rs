@@ -0,0 +1,330 @@
+// Date attributes: parsing with Java-style format strings, as written by
+// Weka, and calendar-aware binning for the histograms.
+
+use chrono::format::{self, Parsed, StrftimeItems};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime,
+             NaiveTime, Timelike};
+
+/// Format of a date attribute.
+///
+/// Dates are stored as milliseconds since the epoch, in UTC.
+pub struct DateFormat {
+    /// The pattern as declared in the header, if any.
+    ///
+    /// Without one, dates are read as ISO-8601.
+    pub pattern: Option<String>,
+    // Equivalent strftime format
+    strftime: String,
+}
+
+// Used to write dates when no pattern was given.
+const ISO_8601: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+impl DateFormat {
+    /// The default format: ISO-8601.
+    pub fn iso() -> Self {
+        DateFormat {
+            pattern: None,
+            strftime: ISO_8601.to_string(),
+        }
+    }
+
+    /// Builds a format from a Java `SimpleDateFormat` pattern.
+    ///
+    /// Returns `None` if the pattern uses unsupported fields.
+    pub fn from_pattern(pattern: &str) -> Option<Self> {
+        Some(DateFormat {
+            pattern: Some(pattern.to_string()),
+            strftime: to_strftime(pattern)?,
+        })
+    }
+
+    /// Parses a date, returning it in milliseconds since the epoch.
+    pub fn parse(&self, s: &str) -> Option<i64> {
+        if self.pattern.is_none() {
+            return parse_iso(s);
+        }
+
+        let mut parsed = Parsed::new();
+        format::parse(&mut parsed, s, StrftimeItems::new(&self.strftime))
+            .ok()?;
+        let date = parsed.to_naive_date().ok()?;
+        let time = if has_time(&parsed) {
+            // Fields left out of the pattern count as 0
+            if parsed.hour_mod_12().is_none() {
+                parsed.set_hour12(12).ok()?;
+            }
+            if parsed.hour_div_12().is_none() {
+                parsed.set_ampm(false).ok()?;
+            }
+            if parsed.minute().is_none() {
+                parsed.set_minute(0).ok()?;
+            }
+            if parsed.second().is_none() {
+                parsed.set_second(0).ok()?;
+            }
+            parsed.to_naive_time().ok()?
+        } else {
+            NaiveTime::MIN
+        };
+        let offset = parsed.offset().unwrap_or(0) as i64;
+        Some(date.and_time(time).and_utc().timestamp_millis() -
+             offset * 1000)
+    }
+
+    /// Formats a date given in milliseconds since the epoch.
+    pub fn format(&self, millis: i64) -> String {
+        format_millis(millis, &self.strftime)
+    }
+}
+
+// Whether the pattern had any time-of-day field.
+fn has_time(parsed: &Parsed) -> bool {
+    parsed.hour_div_12().is_some() || parsed.hour_mod_12().is_some() ||
+    parsed.minute().is_some() || parsed.second().is_some() ||
+    parsed.nanosecond().is_some()
+}
+
+fn parse_iso(s: &str) -> Option<i64> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Some(date.timestamp_millis());
+    }
+    for f in &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(s, f) {
+            return Some(date.and_utc().timestamp_millis());
+        }
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.and_time(NaiveTime::MIN).and_utc().timestamp_millis())
+}
+
+// Translates a Java SimpleDateFormat pattern to a strftime one.
+fn to_strftime(pattern: &str) -> Option<String> {
+    let mut result = String::new();
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\'' {
+            // Quoted literal text; '' stands for a single quote.
+            i += 1;
+            if chars.get(i) == Some(&'\'') {
+                result.push('\'');
+                i += 1;
+                continue;
+            }
+            while i < chars.len() {
+                if chars[i] == '\'' {
+                    if chars.get(i + 1) == Some(&'\'') {
+                        result.push('\'');
+                        i += 2;
+                        continue;
+                    }
+                    break;
+                }
+                push_literal(&mut result, chars[i]);
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+
+        if !c.is_ascii_alphabetic() {
+            push_literal(&mut result, c);
+            i += 1;
+            continue;
+        }
+
+        // Letters come in runs, whose length selects the variant.
+        let mut n = 1;
+        while chars.get(i + n) == Some(&c) {
+            n += 1;
+        }
+        i += n;
+
+        result.push_str(match (c, n) {
+            ('y', 2) => "%y",
+            ('y', _) => "%Y",
+            ('Y', _) => "%G",
+            ('M', 1) | ('L', 1) => "%-m",
+            ('M', 2) | ('L', 2) => "%m",
+            ('M', 3) | ('L', 3) => "%b",
+            ('M', _) | ('L', _) => "%B",
+            ('d', 1) => "%-d",
+            ('d', _) => "%d",
+            ('D', _) => "%j",
+            ('H', 1) | ('k', 1) => "%-H",
+            ('H', _) | ('k', _) => "%H",
+            ('h', 1) | ('K', 1) => "%-I",
+            ('h', _) | ('K', _) => "%I",
+            ('m', 1) => "%-M",
+            ('m', _) => "%M",
+            ('s', 1) => "%-S",
+            ('s', _) => "%S",
+            ('S', _) => "%3f",
+            ('E', 1..=3) => "%a",
+            ('E', _) => "%A",
+            ('u', _) => "%u",
+            ('a', _) => "%p",
+            ('Z', _) => "%z",
+            ('X', _) => "%:z",
+            _ => return None,
+        });
+    }
+
+    Some(result)
+}
+
+fn push_literal(result: &mut String, c: char) {
+    if c == '%' {
+        result.push_str("%%");
+    } else {
+        result.push(c);
+    }
+}
+
+/// Calendar unit used to bin dates.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TimeUnit {
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl TimeUnit {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "hour" => Some(TimeUnit::Hour),
+            "day" => Some(TimeUnit::Day),
+            "week" => Some(TimeUnit::Week),
+            "month" => Some(TimeUnit::Month),
+            "year" => Some(TimeUnit::Year),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TimeUnit::Hour => "hour",
+            TimeUnit::Day => "day",
+            TimeUnit::Week => "week",
+            TimeUnit::Month => "month",
+            TimeUnit::Year => "year",
+        }
+    }
+
+    /// Picks a unit giving a reasonable number of bins for the given span.
+    pub fn for_span(millis: i64) -> Self {
+        let days = millis / (24 * 3600 * 1000);
+        if days <= 3 {
+            TimeUnit::Hour
+        } else if days <= 90 {
+            TimeUnit::Day
+        } else if days <= 2 * 365 {
+            TimeUnit::Week
+        } else if days <= 30 * 365 {
+            TimeUnit::Month
+        } else {
+            TimeUnit::Year
+        }
+    }
+
+    /// Start of the bin containing `date`.
+    fn truncate(self, date: NaiveDateTime) -> NaiveDateTime {
+        let day = date.date();
+        let start = match self {
+            TimeUnit::Hour => return date.with_minute(0)
+                .and_then(|d| d.with_second(0))
+                .and_then(|d| d.with_nanosecond(0))
+                .unwrap_or(date),
+            TimeUnit::Day => day,
+            TimeUnit::Week => {
+                let from_monday = day.weekday().num_days_from_monday();
+                day - Duration::days(from_monday as i64)
+            }
+            TimeUnit::Month => day.with_day(1).unwrap_or(day),
+            TimeUnit::Year => day.with_ordinal(1).unwrap_or(day),
+        };
+        start.and_time(NaiveTime::MIN)
+    }
+
+    // Start of the bin following the one starting at `date`.
+    fn next(self, date: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            TimeUnit::Hour => date + Duration::hours(1),
+            TimeUnit::Day => date + Duration::days(1),
+            TimeUnit::Week => date + Duration::weeks(1),
+            TimeUnit::Month => date + Months::new(1),
+            TimeUnit::Year => date + Months::new(12),
+        }
+    }
+
+    /// strftime format suitable for labelling bins of this unit.
+    pub fn label_format(self) -> &'static str {
+        match self {
+            TimeUnit::Hour => "%Y-%m-%d %H:%M",
+            TimeUnit::Day | TimeUnit::Week => "%Y-%m-%d",
+            TimeUnit::Month => "%Y-%m",
+            TimeUnit::Year => "%Y",
+        }
+    }
+
+    /// Returns the bin boundaries covering `min..=max`, in milliseconds.
+    ///
+    /// There is one more boundary than there are bins.
+    pub fn boundaries(self, min: i64, max: i64) -> Vec<i64> {
+        let (min, max) = match (DateTime::from_timestamp_millis(min),
+                                DateTime::from_timestamp_millis(max)) {
+            (Some(min), Some(max)) => (min.naive_utc(), max.naive_utc()),
+            _ => return Vec::new(),
+        };
+
+        let mut bounds = Vec::new();
+        let mut date = self.truncate(min);
+        while date <= max {
+            bounds.push(date.and_utc().timestamp_millis());
+            date = self.next(date);
+        }
+        bounds.push(date.and_utc().timestamp_millis());
+        bounds
+    }
+}
+
+/// Formats a date for display in the given strftime format.
+pub fn format_millis(millis: i64, fmt: &str) -> String {
+    match DateTime::from_timestamp_millis(millis) {
+        Some(date) => date.format(fmt).to_string(),
+        None => millis.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_partial_time_fields() {
+        let format = DateFormat::from_pattern("yyyy-MM-dd HH").unwrap();
+        let millis = format.parse("2020-01-02 13").unwrap();
+        assert_eq!(format_millis(millis, "%Y-%m-%d %H:%M:%S"),
+                   "2020-01-02 13:00:00");
+
+        let format = DateFormat::from_pattern("yyyy-MM-dd hh:mm").unwrap();
+        let millis = format.parse("2020-01-02 07:30").unwrap();
+        assert_eq!(format_millis(millis, "%Y-%m-%d %H:%M:%S"),
+                   "2020-01-02 07:30:00");
+    }
+
+    #[test]
+    fn date_only_pattern_is_midnight() {
+        let format = DateFormat::from_pattern("dd/MM/yyyy").unwrap();
+        let millis = format.parse("02/01/2020").unwrap();
+        assert_eq!(format_millis(millis, "%Y-%m-%d %H:%M:%S"),
+                   "2020-01-02 00:00:00");
+    }
+}
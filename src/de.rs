@@ -0,0 +1,448 @@
+// Deserialization of instances into user types with serde, so that rows
+// can be read as structs rather than by matching on `Value`.
+//
+// Attributes map to struct fields by name, nominal values to strings or
+// unit enum variants, and missing values to `None`.
+
+use arff::{ArffContent, Attribute, AttributeType, Value};
+use column::Table;
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{self, Deserialize, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use std::error;
+use std::fmt;
+
+/// Error returned when an instance does not fit the requested type.
+#[derive(Debug)]
+pub struct Error {
+    message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error { message: msg.to_string() }
+    }
+}
+
+/// Deserializes instance `row` of `content`.
+pub fn from_row<'a, T: Deserialize<'a>>(content: &'a ArffContent, row: usize)
+                                        -> Result<T, Error> {
+    T::deserialize(RowDeserializer::new(&content.data, content.attributes(), row))
+        .map_err(|e| Error { message: format!("row {}: {}", row, e) })
+}
+
+/// Deserializes all the instances of `content`, in order.
+pub fn rows<'a, T: Deserialize<'a>>(content: &'a ArffContent)
+                                    -> impl Iterator<Item = Result<T, Error>> + 'a {
+    (0..content.data.len()).map(move |row| from_row(content, row))
+}
+
+/// Deserializer for an instance: a map from attribute names to values as
+/// a struct or map, or the sequence of values as a tuple.
+pub struct RowDeserializer<'a> {
+    table: &'a Table,
+    attributes: &'a [Attribute],
+    row: usize,
+}
+
+impl<'a> RowDeserializer<'a> {
+    pub(crate) fn new(table: &'a Table, attributes: &'a [Attribute], row: usize) -> Self {
+        RowDeserializer {
+            table,
+            attributes,
+            row,
+        }
+    }
+
+    fn value(&self, att: usize) -> ValueDeserializer<'a> {
+        ValueDeserializer {
+            attr: &self.attributes[att],
+            value: self.table.value(self.attributes, self.row, att),
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let atts = (0..self.attributes.len()).collect();
+        visitor.visit_map(RowAccess::new(self, atts))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str,
+                                           fields: &'static [&'static str], visitor: V)
+                                           -> Result<V::Value, Error> {
+        // Only visit the attributes asked for. Others are left for serde to
+        // complain about, or to default.
+        let atts = fields.iter()
+            .filter_map(|field| self.attributes.iter().position(|attr| attr.name == *field))
+            .collect();
+        visitor.visit_map(RowAccess::new(self, atts))
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let atts = (0..self.attributes.len()).collect();
+        visitor.visit_seq(RowAccess::new(self, atts))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V)
+                                          -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize,
+                                                 visitor: V)
+                                                 -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct map enum identifier ignored_any
+    }
+}
+
+// Walks through some attributes of an instance, as a map or a sequence.
+struct RowAccess<'a> {
+    row: RowDeserializer<'a>,
+    atts: Vec<usize>,
+    next: usize,
+}
+
+impl<'a> RowAccess<'a> {
+    fn new(row: RowDeserializer<'a>, atts: Vec<usize>) -> Self {
+        RowAccess { row, atts, next: 0 }
+    }
+
+    fn next_value<'de, T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, Error>
+        where 'a: 'de
+    {
+        let value = self.row.value(self.atts[self.next]);
+        self.next += 1;
+        let name = &value.attr.name;
+        seed.deserialize(value)
+            .map_err(|e| Error { message: format!("attribute `{}`: {}", name, e) })
+    }
+}
+
+impl<'de> de::MapAccess<'de> for RowAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K)
+                                              -> Result<Option<K::Value>, Error> {
+        match self.atts.get(self.next) {
+            None => Ok(None),
+            Some(&att) => {
+                let name: &'de str = &self.row.attributes[att].name;
+                seed.deserialize(BorrowedStrDeserializer::new(name)).map(Some)
+            }
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        self.next_value(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.atts.len() - self.next)
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for RowAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T)
+                                                  -> Result<Option<T::Value>, Error> {
+        if self.next == self.atts.len() {
+            return Ok(None);
+        }
+        self.next_value(seed).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.atts.len() - self.next)
+    }
+}
+
+// Walks through the sub-instances of a bag.
+struct BagAccess<'a> {
+    table: &'a Table,
+    attributes: &'a [Attribute],
+    rows: ::std::ops::Range<usize>,
+}
+
+impl<'de> de::SeqAccess<'de> for BagAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T)
+                                                  -> Result<Option<T::Value>, Error> {
+        match self.rows.next() {
+            None => Ok(None),
+            Some(row) => {
+                seed.deserialize(RowDeserializer::new(self.table, self.attributes, row))
+                    .map(Some)
+            }
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.rows.len())
+    }
+}
+
+/// Deserializer for a single value.
+///
+/// Numbers deserialize into any numeric type they fit in, dates into
+/// milliseconds since the epoch (or their text for strings), nominal
+/// values into strings or unit enum variants, and relational values into
+/// sequences of instances.
+pub struct ValueDeserializer<'a> {
+    attr: &'a Attribute,
+    value: Value<'a>,
+}
+
+impl<'a> ValueDeserializer<'a> {
+    fn missing(&self) -> Error {
+        Error { message: "missing value".to_string() }
+    }
+
+    fn out_of_range(&self, f: f32) -> Error {
+        de::Error::invalid_value(de::Unexpected::Float(f64::from(f)), &"a 64-bit integer")
+    }
+
+    // Text of a nominal value.
+    fn token(&self, i: usize) -> &'a str {
+        match self.attr.att_type.tokens() {
+            Some(tokens) => &tokens[i],
+            None => "",
+        }
+    }
+}
+
+// Integer fields accept numbers without a fractional part. Casts would
+// saturate, so numbers beyond 64 bits are rejected first.
+macro_rules! deserialize_integer {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.value {
+                    Value::Numeric(f) if f.fract() == 0.0 && f < 0.0 => {
+                        if f < i64::MIN as f32 {
+                            return Err(self.out_of_range(f));
+                        }
+                        visitor.visit_i64(f as i64)
+                    }
+                    Value::Numeric(f) if f.fract() == 0.0 => {
+                        if f >= u64::MAX as f32 {
+                            return Err(self.out_of_range(f));
+                        }
+                        visitor.visit_u64(f as u64)
+                    }
+                    Value::Missing => Err(self.missing()),
+                    _ => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Numeric(f) => visitor.visit_f32(f),
+            Value::Date(d) => visitor.visit_i64(d),
+            Value::Text(i) => visitor.visit_borrowed_str(self.token(i)),
+            Value::String(s) => visitor.visit_borrowed_str(s),
+            Value::Relational(bag) => {
+                visitor.visit_seq(BagAccess {
+                    table: bag.table(),
+                    attributes: self.attr.att_type.relation().unwrap_or(&[]),
+                    rows: bag.rows(),
+                })
+            }
+            Value::Missing => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Missing => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Missing => Err(self.missing()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Numeric(f) => visitor.visit_f64(f64::from(f)),
+            Value::Missing => Err(self.missing()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_integer! {
+        deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Date(d) => match self.attr.att_type {
+                AttributeType::Date(ref format) => visitor.visit_string(format.format(d)),
+                _ => self.deserialize_any(visitor),
+            },
+            Value::Missing => Err(self.missing()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str,
+                                         _variants: &'static [&'static str], visitor: V)
+                                         -> Result<V::Value, Error> {
+        match self.value {
+            Value::Text(i) => visitor.visit_enum(self.token(i).into_deserializer()),
+            Value::Missing => Err(self.missing()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V)
+                                                   -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool char bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arff::ParseMode;
+
+    fn load(text: &str) -> ArffContent {
+        match ArffContent::from_reader(text.as_bytes(), "test.arff", ParseMode::Strict) {
+            Ok(content) => content,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Colour {
+        Red,
+        Blue,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Row {
+        count: u32,
+        colour: Colour,
+        label: String,
+        size: Option<f32>,
+    }
+
+    #[test]
+    fn maps_fields_by_name() {
+        let content = load("@relation r\n\
+                            @attribute size numeric\n\
+                            @attribute ignored string\n\
+                            @attribute label {red,blue}\n\
+                            @attribute colour {red,blue}\n\
+                            @attribute count integer\n\
+                            @data\n\
+                            1.5,x,blue,red,3\n\
+                            ?,y,red,blue,0\n");
+        let rows: Vec<Row> = rows(&content).collect::<Result<_, _>>().unwrap();
+        assert_eq!(rows,
+                   [Row {
+                        count: 3,
+                        colour: Colour::Red,
+                        label: "blue".to_string(),
+                        size: Some(1.5),
+                    },
+                    Row {
+                        count: 0,
+                        colour: Colour::Blue,
+                        label: "red".to_string(),
+                        size: None,
+                    }]);
+
+        let tuple: (Option<f32>, &str, String, Colour, i64) = from_row(&content, 1).unwrap();
+        assert_eq!(tuple, (None, "y", "red".to_string(), Colour::Blue, 0));
+    }
+
+    #[test]
+    fn rejects_bad_integers() {
+        let content = load("@relation r\n@attribute n numeric\n@data\n2.5\n1e30\n-3\n?\n");
+        assert!(from_row::<(i32,)>(&content, 0).is_err());
+        assert_eq!(from_row::<(f64,)>(&content, 0).unwrap(), (2.5,));
+
+        assert!(from_row::<(u64,)>(&content, 1).is_err());
+        assert!(from_row::<(i64,)>(&content, 1).is_err());
+
+        assert!(from_row::<(u64,)>(&content, 2).is_err());
+        assert_eq!(from_row::<(i8,)>(&content, 2).unwrap(), (-3,));
+
+        assert!(from_row::<(i32,)>(&content, 3).is_err());
+        assert_eq!(from_row::<(Option<i32>,)>(&content, 3).unwrap(), (None,));
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Point {
+        x: f32,
+        tag: Option<String>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Sample {
+        id: u8,
+        points: Option<Vec<Point>>,
+    }
+
+    #[test]
+    fn reads_bags_into_vectors() {
+        let content = load("@relation r\n\
+                            @attribute id numeric\n\
+                            @attribute points relational\n\
+                            @attribute x numeric\n\
+                            @attribute tag {a,b}\n\
+                            @end points\n\
+                            @data\n\
+                            1,'1,a\\n2,?'\n\
+                            2,''\n\
+                            3,?\n");
+        let samples: Vec<Sample> = rows(&content).collect::<Result<_, _>>().unwrap();
+        assert_eq!(samples[0].points,
+                   Some(vec![Point {
+                                 x: 1.0,
+                                 tag: Some("a".to_string()),
+                             },
+                             Point { x: 2.0, tag: None }]));
+        assert_eq!(samples[1].points, Some(Vec::new()));
+        assert_eq!(samples[2].points, None);
+    }
+}
@@ -0,0 +1,99 @@
+// Reading of compressed files. The format is recognized by its magic bytes,
+// so that the file name does not matter.
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use std::io::{self, BufRead, Read};
+use std::path;
+use xz2::read::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+const GZIP: &[u8] = &[0x1f, 0x8b];
+const BZIP2: &[u8] = b"BZh";
+const XZ: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const ZSTD: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Wraps `reader` in a decoder if its content is compressed with gzip,
+/// bzip2, xz or zstd. Other content is read as is.
+pub(crate) fn decoder<'a, R: BufRead + 'a>(mut reader: R)
+                                   -> io::Result<Box<dyn Read + 'a>> {
+    // A single read may return fewer bytes than the longest magic.
+    let mut buffer = [0; 6];
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    let magic = &buffer[..len];
+    let reader = io::Cursor::new(magic.to_vec()).chain(reader);
+    Ok(if magic.starts_with(GZIP) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else if magic.starts_with(BZIP2) {
+        Box::new(MultiBzDecoder::new(reader))
+    } else if magic.starts_with(XZ) {
+        Box::new(XzDecoder::new_multi_decoder(reader))
+    } else if magic.starts_with(ZSTD) {
+        Box::new(ZstdDecoder::with_buffer(reader)?)
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Returns the extension telling the format of a file, ignoring the one of
+/// the compression, if any.
+pub(crate) fn format_of(filename: &path::Path) -> Option<&str> {
+    let name = filename.file_name()?.to_str()?;
+    let name = [".gz", ".bz2", ".xz", ".zst"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name);
+    name.rsplit_once('.').map(|(_, ext)| ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    // Hands out one byte per read.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    fn read_all<R: BufRead>(reader: R) -> String {
+        let mut text = String::new();
+        decoder(reader).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn sniffs_magic_across_short_reads() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"@relation test").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let reader = io::BufReader::with_capacity(1, Trickle(&compressed));
+        assert_eq!(read_all(reader), "@relation test");
+    }
+
+    #[test]
+    fn passes_short_plain_text_through() {
+        let reader = io::BufReader::with_capacity(1, Trickle(b"abc"));
+        assert_eq!(read_all(reader), "abc");
+    }
+}
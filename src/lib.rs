@@ -0,0 +1,54 @@
+//! Reading, writing and viewing of Weka's arff datasets.
+//!
+//! `arff::ArffContent` holds a dataset. Besides arff files, it can be
+//! loaded from CSV (`csv_import`), XRFF (`xrff`) and LibSVM (`libsvm`)
+//! files, or built in memory (`builder`). It can be written back with
+//! `writer` and `xrff`, converted with `convert`, deserialized into user
+//! types with `de`, and browsed with `visu`.
+
+// These are for the Iron web framework
+extern crate iron;
+extern crate staticfile;
+extern crate router;
+extern crate mount;
+extern crate handlebars_iron as hbs;
+extern crate urlencoded;
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+// For the columnar exports
+extern crate arrow_array;
+extern crate arrow_buffer;
+extern crate arrow_ipc;
+extern crate arrow_schema;
+extern crate parquet;
+
+extern crate chrono;
+extern crate csv;
+extern crate bzip2;
+extern crate flate2;
+extern crate xml;
+extern crate xz2;
+extern crate zstd;
+
+pub mod arff;
+pub mod builder;
+pub(crate) mod column;
+pub mod convert;
+pub mod csv_import;
+pub mod date;
+pub mod de;
+pub(crate) mod decompress;
+pub mod libsvm;
+pub mod stats;
+pub(crate) mod tokenizer;
+pub mod visu;
+pub mod writer;
+pub mod xrff;
+
+pub use arff::{ArffContent, ArffError, Attribute, AttributeType, Bag, Instance, ParseMode,
+               Value};
+pub use builder::ArffBuilder;
@@ -0,0 +1,320 @@
+// Loading of LibSVM / SVMlight files, where each line holds a label
+// followed by `index:value` pairs. Features left out of a line are zero,
+// which is what sparse tables give for numeric attributes.
+
+use arff::{self, ArffContent, ArffError, ArffErrorKind, Attribute, AttributeType,
+           Diagnostic, Diagnostics, ParseMode};
+use column::Table;
+use decompress;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Read};
+use std::path;
+use std::str::FromStr;
+
+/// Returns `true` if the file should be read as LibSVM.
+pub fn is_libsvm(filename: &path::Path) -> bool {
+    matches!(decompress::format_of(filename),
+             Some("libsvm") | Some("svm") | Some("svmlight"))
+}
+
+/// The file naming the features of a LibSVM file, one per line in index
+/// order: `data.libsvm.gz` is described by `data.names`.
+pub fn names_path(filename: &path::Path) -> path::PathBuf {
+    let mut names = filename.to_path_buf();
+    if decompress::format_of(filename) != filename.extension().and_then(|ext| ext.to_str()) {
+        // Compressed: drop that extension first
+        names.set_extension("");
+    }
+    names.set_extension("names");
+    names
+}
+
+// A parsed line, before the attributes are known.
+struct Row {
+    label: String,
+    // Feature indices, ascending, with their value or `None` if bad
+    features: Vec<(usize, Option<f32>)>,
+}
+
+// Parses a line. Bad values are reported, and kept as `None`.
+fn parse_line(text: &str, mode: ParseMode, problems: &mut Vec<ArffError>)
+              -> Result<Option<Row>, ArffError> {
+    // Anything after `#` is a comment
+    let text = text.split('#').next().unwrap_or("");
+    let mut tokens = text.split_whitespace();
+    let label = match tokens.next() {
+        Some(label) => label.to_string(),
+        None => return Ok(None),
+    };
+
+    let mut features: Vec<(usize, Option<f32>)> = Vec::new();
+    for token in tokens {
+        let (index, value) = match token.split_once(':') {
+            Some(pair) => pair,
+            None => {
+                let mut error = ArffError::new(ArffErrorKind::Syntax("expected index:value"));
+                error.token = Some(token.to_string());
+                return Err(error);
+            }
+        };
+        // SVMlight query ids do not describe the instance
+        if index == "qid" {
+            continue;
+        }
+
+        let index = match usize::from_str(index) {
+            Ok(index) if features.last().is_none_or(|&(last, _)| index > last) => index,
+            _ => {
+                let mut error = ArffError::new(ArffErrorKind::BadIndex);
+                error.token = Some(index.to_string());
+                return Err(error);
+            }
+        };
+        let value = match arff::parse_f32(value) {
+            Ok(value) => Some(value),
+            Err(kind) => {
+                let mut error = ArffError::new(kind);
+                error.token = Some(value.to_string());
+                arff::report(mode, problems, error)?;
+                None
+            }
+        };
+        features.push((index, value));
+    }
+    Ok(Some(Row { label, features }))
+}
+
+// Distinct labels, in numeric order if they are all numbers, in order of
+// appearance otherwise, with the index of each label as written. Labels of
+// equal value, such as `1`, `+1` and `1.0`, are the same class, named
+// after its first spelling.
+fn labels(rows: &[Row]) -> (Vec<String>, HashMap<&str, usize>) {
+    let mut indices = HashMap::new();
+    let mut labels: Vec<&str> = Vec::new();
+    for row in rows {
+        if !indices.contains_key(&*row.label) {
+            indices.insert(&*row.label, labels.len());
+            labels.push(&row.label);
+        }
+    }
+
+    let numbers: Option<Vec<f32>> = labels.iter()
+        .map(|label| arff::parse_f32(label).ok())
+        .collect();
+    let numbers = match numbers {
+        Some(numbers) => numbers,
+        None => return (labels.iter().map(|label| label.to_string()).collect(), indices),
+    };
+
+    // Stable, so the first spelling of a value comes first
+    let mut order: Vec<usize> = (0..labels.len()).collect();
+    order.sort_by(|&a, &b| numbers[a].total_cmp(&numbers[b]));
+    let mut classes: Vec<String> = Vec::new();
+    let mut class_of = vec![0; labels.len()];
+    for (k, &i) in order.iter().enumerate() {
+        if k == 0 || numbers[order[k - 1]] != numbers[i] {
+            classes.push(labels[i].to_string());
+        }
+        class_of[i] = classes.len() - 1;
+    }
+    for index in indices.values_mut() {
+        *index = class_of[*index];
+    }
+    (classes, indices)
+}
+
+/// Loads a LibSVM file, with the feature names from its `names_path` if
+/// there is one.
+pub fn load(filename: &path::Path, mode: ParseMode) -> Result<ArffContent, ArffError> {
+    let name = filename.display().to_string();
+    let io_error = |e, name: &str| ArffError::new(ArffErrorKind::Io(e)).located(name, None);
+
+    let file = fs::File::open(filename).map_err(|e| io_error(e, &name))?;
+    let names_path = names_path(filename);
+    let title = names_path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| name.clone());
+
+    let names = match fs::read_to_string(&names_path) {
+        Ok(text) => text.lines().map(|line| line.trim().to_string()).collect(),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(io_error(e, &names_path.display().to_string())),
+    };
+    from_reader(file, &name, &title, &names, mode)
+}
+
+/// Loads LibSVM content from any reader. Features are named after `names`
+/// as far as it goes, and after their index beyond.
+pub fn from_reader<R: Read>(reader: R, name: &str, title: &str, names: &[String],
+                            mode: ParseMode)
+                            -> Result<ArffContent, ArffError> {
+    let reader = decompress::decoder(io::BufReader::new(reader))
+        .map_err(|e| ArffError::new(ArffErrorKind::Io(e)).located(name, None))?;
+    let reader = io::BufReader::new(reader);
+
+    let mut rows = Vec::new();
+    let mut diagnostics = Diagnostics::new();
+    for (i, text) in reader.lines().enumerate() {
+        let line_no = Some(i + 1);
+        let text = text.map_err(|e| ArffError::new(ArffErrorKind::Io(e)).located(name, line_no))?;
+
+        let mut problems = Vec::new();
+        let parsed = match parse_line(&text, mode, &mut problems) {
+            Ok(parsed) => parsed,
+            // Skip the malformed line in lenient mode
+            Err(error) => {
+                arff::report(mode, &mut problems, error).map_err(|e| e.located(name, line_no))?;
+                None
+            }
+        };
+        for error in problems {
+            diagnostics.push(Diagnostic {
+                error: error.located(name, line_no),
+                text: text.clone(),
+            });
+        }
+        rows.extend(parsed);
+    }
+
+    // Indices usually start at 1, but some files use 0
+    let first = rows.iter()
+        .filter_map(|row| row.features.first())
+        .map(|&(index, _)| index.min(1))
+        .min()
+        .unwrap_or(1);
+    let last = rows.iter()
+        .filter_map(|row| row.features.last())
+        .map(|&(index, _)| index)
+        .max()
+        .unwrap_or(0);
+    let count = (last + 1 - first).max(names.len());
+
+    let mut attributes: Vec<Attribute> = (0..count)
+        .map(|att| Attribute {
+            name: names.get(att)
+                .cloned()
+                .unwrap_or_else(|| format!("att_{}", att + first)),
+            att_type: AttributeType::Numeric,
+        })
+        .collect();
+    let (labels, label_ids) = labels(&rows);
+    let class = attributes.len();
+    attributes.push(Attribute {
+        // Features may be named `class` too
+        name: arff::unique_name(&attributes, "class"),
+        att_type: AttributeType::Text(labels),
+    });
+
+    let mut data = Table::new(&attributes, true);
+    for row in &rows {
+        let index = data.len();
+        for &(feature, value) in &row.features {
+            let column = data.column_mut(feature - first);
+            match value {
+                Some(value) => column.push_num(index, value),
+                None => column.push_missing(index),
+            }
+        }
+        // Labels were collected from the rows
+        data.column_mut(class).push_token(index, label_ids[&*row.label]);
+        data.end_row(1.0);
+    }
+
+    let mut content = ArffContent::from_table(name, title, attributes, data);
+    content.set_class_attribute(Some(class))?;
+    content.set_diagnostics(diagnostics);
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arff::Value;
+
+    fn parse(text: &str) -> Row {
+        parse_line(text, ParseMode::Strict, &mut Vec::new()).ok().unwrap().unwrap()
+    }
+
+    fn load(text: &str, names: &[&str], mode: ParseMode) -> Result<ArffContent, ArffError> {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        from_reader(text.as_bytes(), "test.libsvm", "test", &names, mode)
+    }
+
+    #[test]
+    fn parses_lines() {
+        let row = parse("+1 qid:3 1:0.5 4:-2 # comment 5:1");
+        assert_eq!(row.label, "+1");
+        assert_eq!(row.features, [(1, Some(0.5)), (4, Some(-2.0))]);
+
+        assert!(parse_line("  # only a comment", ParseMode::Strict, &mut Vec::new())
+            .ok()
+            .unwrap()
+            .is_none());
+        assert!(parse_line("1 2:1 1:1", ParseMode::Strict, &mut Vec::new()).is_err());
+        assert!(parse_line("1 2", ParseMode::Strict, &mut Vec::new()).is_err());
+
+        let mut problems = Vec::new();
+        let row = parse_line("1 1:x 2:3", ParseMode::Lenient, &mut problems).ok().unwrap();
+        assert_eq!(row.unwrap().features, [(1, None), (2, Some(3.0))]);
+        assert_eq!(problems.len(), 1);
+    }
+
+    #[test]
+    fn merges_equal_labels() {
+        let rows: Vec<Row> = ["1", "-1", "+1", "1.0", "0.5"].iter()
+            .map(|label| parse(label))
+            .collect();
+        let (classes, ids) = labels(&rows);
+        assert_eq!(classes, ["-1", "0.5", "1"]);
+        assert_eq!(ids["1"], 2);
+        assert_eq!(ids["+1"], 2);
+        assert_eq!(ids["1.0"], 2);
+        assert_eq!(ids["-1"], 0);
+
+        let rows: Vec<Row> = ["b", "a", "b", "1"].iter().map(|label| parse(label)).collect();
+        let (classes, ids) = labels(&rows);
+        assert_eq!(classes, ["b", "a", "1"]);
+        assert_eq!(ids["1"], 2);
+    }
+
+    #[test]
+    fn finds_the_names_file() {
+        assert_eq!(names_path(path::Path::new("dir/data.libsvm")),
+                   path::Path::new("dir/data.names"));
+        assert_eq!(names_path(path::Path::new("data.svm.gz")),
+                   path::Path::new("data.names"));
+    }
+
+    #[test]
+    fn loads_one_based_features() {
+        let content = load("1 1:0.5 3:2\n-1 2:1\n", &["a", "class"], ParseMode::Strict).unwrap();
+        let names: Vec<&str> = content.attributes().iter().map(|attr| &*attr.name).collect();
+        assert_eq!(names, ["a", "class", "att_3", "class_"]);
+        assert_eq!(content.class_attribute(), Some(3));
+        assert_eq!(content.value(0, 0).num(), Some(0.5));
+        assert_eq!(content.value(0, 1).num(), Some(0.0));
+        assert_eq!(content.value(0, 2).num(), Some(2.0));
+        assert_eq!(content.value(1, 3).text(), Some(0));
+    }
+
+    #[test]
+    fn loads_zero_based_features() {
+        let content = load("0 0:1 2:3\n1 1:2\n", &[], ParseMode::Strict).unwrap();
+        let names: Vec<&str> = content.attributes().iter().map(|attr| &*attr.name).collect();
+        assert_eq!(names, ["att_0", "att_1", "att_2", "class"]);
+        assert_eq!(content.value(0, 0).num(), Some(1.0));
+        assert_eq!(content.value(1, 1).num(), Some(2.0));
+    }
+
+    #[test]
+    fn lenient_skips_bad_lines() {
+        let text = "1 1:1\n1 oops\n-1 2:x\n";
+        assert!(load(text, &[], ParseMode::Strict).is_err());
+
+        let content = load(text, &[], ParseMode::Lenient).unwrap();
+        assert_eq!(content.len(), 2);
+        assert_eq!(content.diagnostics().len(), 2);
+        assert!(matches!(content.value(1, 1), Value::Missing));
+    }
+}
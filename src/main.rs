@@ -1,29 +1,22 @@
-// These are for the Iron web framework
-extern crate iron;
-extern crate staticfile;
-extern crate router;
-extern crate mount;
-extern crate handlebars_iron as hbs;
-extern crate urlencoded;
-
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
-extern crate serde_json;
 
 extern crate xdg_basedir;
 extern crate getopts;
 extern crate toml;
 
-mod arff;
-mod visu;
+extern crate varf;
 
 use getopts::Options;
 use std::env;
 use std::fs::File;
+use std::io;
+use std::io::Read;
 use std::path;
+use std::process;
 use std::str::FromStr;
-use std::io::Read;
+use varf::{arff, convert, csv_import, libsvm, visu, xrff};
 
 #[derive(Deserialize)]
 struct Config {
@@ -42,9 +35,8 @@ fn get_default_port() -> u16 {
         Err(_) => return default,
     };
     let mut content = String::new();
-    match file.read_to_string(&mut content) {
-        Err(_) => return default,
-        Ok(_) => (),
+    if file.read_to_string(&mut content).is_err() {
+        return default;
     }
 
     let table: Config = match toml::de::from_str(&content) {
@@ -52,9 +44,7 @@ fn get_default_port() -> u16 {
         _ => return default,
     };
 
-    let port = table.port.unwrap_or(default);
-
-    port
+    table.port.unwrap_or(default)
 }
 
 fn get_data_dir() -> &'static str {
@@ -66,12 +56,26 @@ fn get_data_dir() -> &'static str {
     }
 }
 
+const USAGE: &str = "Usage: varf [OPTIONS] FILENAME
+       varf [OPTIONS] convert FILENAME OUTPUT
+
+Use - as FILENAME to read from the standard input.
+The convert command writes the dataset to OUTPUT instead of viewing it,
+as .parquet, .arrow, .arff or .xrff depending on its extension.";
+
 struct Params {
     filename: String,
+    // Where to convert the file to, instead of serving it
+    output: Option<String>,
     datadir: String,
     port: u16,
 
     open_browser: bool,
+    mode: arff::ParseMode,
+
+    // Type overrides for csv columns
+    nominal: Vec<String>,
+    string: Vec<String>,
 }
 
 fn read_params() -> Result<Params, String> {
@@ -85,6 +89,17 @@ fn read_params() -> Result<Params, String> {
                          get_data_dir()),
                 "VARF_HOME");
     opts.optflag("o", "open", "Open the page in the browser");
+    opts.optflag("l",
+                 "lenient",
+                 "Replace bad values with missing ones instead of failing.");
+    opts.optmulti("",
+                  "nominal",
+                  "Reads a csv column as nominal, by name or index.",
+                  "COLUMN");
+    opts.optmulti("",
+                  "string",
+                  "Reads a csv column as string, by name or index.",
+                  "COLUMN");
 
     let mut matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -92,15 +107,24 @@ fn read_params() -> Result<Params, String> {
     };
 
     if matches.opt_present("h") {
-        return Err(opts.usage("Usage: varf [OPTIONS] FILENAME"));
+        return Err(opts.usage(USAGE));
     }
 
     if matches.free.is_empty() {
         println!("Error: no filename given!");
-        return Err(opts.usage("Usage: varf [OPTIONS] FILENAME"));
+        return Err(opts.usage(USAGE));
     }
 
-    let filename = matches.free.remove(0);
+    let (filename, output) = if matches.free[0] == "convert" {
+        if matches.free.len() != 3 {
+            println!("Error: convert needs an input and an output filename!");
+            return Err(opts.usage(USAGE));
+        }
+        let output = matches.free.remove(2);
+        (matches.free.remove(1), Some(output))
+    } else {
+        (matches.free.remove(0), None)
+    };
 
     let port = match matches.opt_str("p") {
         None => get_default_port(),
@@ -111,12 +135,21 @@ fn read_params() -> Result<Params, String> {
         Some(datadir) => datadir,
     };
     let open_browser = matches.opt_present("o");
+    let mode = if matches.opt_present("l") {
+        arff::ParseMode::Lenient
+    } else {
+        arff::ParseMode::Strict
+    };
 
     Ok(Params {
-        filename: filename,
-        datadir: datadir,
-        port: port,
-        open_browser: open_browser,
+        filename,
+        output,
+        datadir,
+        port,
+        open_browser,
+        mode,
+        nominal: matches.opt_strs("nominal"),
+        string: matches.opt_strs("string"),
     })
 }
 
@@ -130,7 +163,42 @@ fn main() {
         Ok(params) => params,
     };
 
-    let content = arff::ArffContent::new(path::Path::new(&params.filename));
+    let path = path::Path::new(&params.filename);
+    println!("Loading {}...", params.filename);
+    // `-` stands for the standard input
+    let loaded = if params.filename == "-" {
+        arff::ArffContent::from_reader(io::stdin(), "<stdin>", params.mode)
+    } else if csv_import::is_delimited(path) {
+        let mut options = csv_import::CsvOptions::for_path(path);
+        options.nominal = params.nominal;
+        options.string = params.string;
+        options.mode = params.mode;
+        csv_import::load(path, &options)
+    } else if libsvm::is_libsvm(path) {
+        libsvm::load(path, params.mode)
+    } else if xrff::is_xrff(path) {
+        xrff::load(path, params.mode)
+    } else {
+        arff::ArffContent::new(path, params.mode)
+    };
+    let content = match loaded {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    if !content.diagnostics().is_empty() {
+        println!("{} problems found while loading.", content.diagnostics().len());
+    }
+
+    if let Some(output) = params.output {
+        if let Err(e) = convert::convert(&content, path::Path::new(&output)) {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
 
     visu::serve_result(&params.datadir, params.port, &content, params.open_browser);
 }
@@ -0,0 +1,509 @@
+// Descriptive statistics over weighted samples, for the attribute view.
+//
+// Weights count as frequencies: an instance of weight 2 counts as two.
+
+/// Summary of the values of a numeric attribute.
+pub struct Summary {
+    /// Total weight of the values.
+    pub weight: f64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Sample standard deviation, 0 for a single value.
+    pub std: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+}
+
+/// Value below which a fraction `p` of the total weight lies, in values
+/// sorted by increasing value.
+///
+/// When `p` falls exactly between two values, returns their average, so
+/// that the median of an even number of values is the usual one.
+pub fn quantile(sorted: &[(f64, f64)], total: f64, p: f64) -> f64 {
+    let target = p * total;
+    let mut cumulated = 0.0;
+    for (i, &(x, w)) in sorted.iter().enumerate() {
+        cumulated += w;
+        if cumulated > target {
+            return x;
+        }
+        if cumulated == target {
+            // Average with the next value that has some weight
+            return match sorted[i + 1..].iter().find(|&&(_, w)| w > 0.0) {
+                Some(&(next, _)) => (x + next) / 2.0,
+                None => x,
+            };
+        }
+    }
+    sorted.last().map_or(f64::NAN, |&(x, _)| x)
+}
+
+/// Summarizes `(value, weight)` pairs sorted by value. Returns `None` if
+/// they have no weight.
+pub fn summarize(sorted: &[(f64, f64)]) -> Option<Summary> {
+    let weight: f64 = sorted.iter().map(|&(_, w)| w).sum();
+    if weight <= 0.0 {
+        return None;
+    }
+
+    let mean = sorted.iter().map(|&(x, w)| x * w).sum::<f64>() / weight;
+    let squares: f64 = sorted.iter().map(|&(x, w)| w * (x - mean) * (x - mean)).sum();
+    let std = if weight > 1.0 {
+        (squares / (weight - 1.0)).sqrt()
+    } else {
+        0.0
+    };
+
+    // Skip the values of weight 0 at the ends
+    let weighted = || sorted.iter().filter(|&&(_, w)| w > 0.0);
+    Some(Summary {
+        weight,
+        min: weighted().next().map_or(f64::NAN, |&(x, _)| x),
+        max: weighted().next_back().map_or(f64::NAN, |&(x, _)| x),
+        mean,
+        std,
+        q1: quantile(sorted, weight, 0.25),
+        median: quantile(sorted, weight, 0.5),
+        q3: quantile(sorted, weight, 0.75),
+    })
+}
+
+/// Index of the largest count, the first one on ties.
+pub fn mode(counts: &[f64]) -> Option<usize> {
+    let mut best = None;
+    for (i, &count) in counts.iter().enumerate() {
+        if count > 0.0 && best.is_none_or(|b: usize| count > counts[b]) {
+            best = Some(i);
+        }
+    }
+    best
+}
+
+/// Shannon entropy of a distribution given by counts, in bits.
+pub fn entropy(counts: &[f64]) -> f64 {
+    let total: f64 = counts.iter().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    -counts.iter()
+        .filter(|&&count| count > 0.0)
+        .map(|&count| {
+            let p = count / total;
+            p * p.log2()
+        })
+        .sum::<f64>()
+}
+
+/// Formats a statistic with a few significant digits.
+pub fn format(x: f64) -> String {
+    if x.is_nan() {
+        return "-".to_string();
+    }
+    if x == 0.0 {
+        // Including -0
+        return "0".to_string();
+    }
+    if x.fract() == 0.0 && x.abs() < 1e15 {
+        return format!("{}", x);
+    }
+    if x.abs() >= 1e-3 && x.abs() < 1e6 {
+        // Four significant digits
+        let decimals = (3 - x.abs().log10().floor() as i32).max(0) as usize;
+        let text = format!("{:.*}", decimals, x);
+        let text = text.trim_end_matches('0').trim_end_matches('.');
+        return text.to_string();
+    }
+    format!("{:.3e}", x)
+}
+
+/// How much knowing an attribute tells about the class, all in bits or as
+/// ratios of them.
+pub struct Relevance {
+    /// Reduction of the class entropy once the attribute is known.
+    pub info_gain: f64,
+    /// Information gain over the entropy of the attribute, so that
+    /// attributes with many values are not favoured.
+    pub gain_ratio: f64,
+    /// Information gain over the mean entropy of the attribute and class,
+    /// between 0 and 1.
+    pub symmetric_uncertainty: f64,
+}
+
+/// Relevance of an attribute, from the weight of each class (columns)
+/// for each of its values (rows).
+pub fn relevance(table: &[Vec<f64>]) -> Relevance {
+    let n_classes = table.iter().map(Vec::len).max().unwrap_or(0);
+    let mut class_counts = vec![0.0; n_classes];
+    for row in table {
+        for (k, &count) in row.iter().enumerate() {
+            class_counts[k] += count;
+        }
+    }
+    let value_counts: Vec<f64> = table.iter().map(|row| row.iter().sum()).collect();
+    let total: f64 = value_counts.iter().sum();
+
+    let class_entropy = entropy(&class_counts);
+    let value_entropy = entropy(&value_counts);
+    // Entropy of the class among the instances of each value
+    let conditional = if total > 0.0 {
+        table.iter()
+            .zip(&value_counts)
+            .map(|(row, &count)| count / total * entropy(row))
+            .sum()
+    } else {
+        0.0
+    };
+
+    // Rounding may make it slightly negative
+    let info_gain = (class_entropy - conditional).max(0.0);
+    let ratio = |denominator: f64| if denominator > 0.0 {
+        info_gain / denominator
+    } else {
+        0.0
+    };
+    Relevance {
+        info_gain,
+        gain_ratio: ratio(value_entropy),
+        symmetric_uncertainty: 2.0 * ratio(value_entropy + class_entropy),
+    }
+}
+
+/// Natural logarithm of the gamma function, for `x > 0`.
+pub fn ln_gamma(x: f64) -> f64 {
+    // Lanczos approximation, with g = 7
+    const COEFFICIENTS: [f64; 9] = [0.999_999_999_999_809_9,
+                                    676.520_368_121_885_1,
+                                    -1_259.139_216_722_402_8,
+                                    771.323_428_777_653_1,
+                                    -176.615_029_162_140_6,
+                                    12.507_343_278_686_905,
+                                    -0.138_571_095_265_720_12,
+                                    9.984_369_578_019_572e-6,
+                                    1.505_632_735_149_311_6e-7];
+    if x < 0.5 {
+        // Reflection formula
+        return (::std::f64::consts::PI / (::std::f64::consts::PI * x).sin()).ln() -
+               ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, &c)| sum + c / (x + (i + 1) as f64));
+    0.5 * (2.0 * ::std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// Precision and iteration limit of the series below
+const EPSILON: f64 = 1e-15;
+const ITERATIONS: usize = 500;
+
+/// Upper regularized incomplete gamma function Q(a, x), for `a > 0`.
+pub fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    // exp(-x) x^a / Gamma(a)
+    let factor = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        // Series of P(a, x), which converges quickly there
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for n in 1..ITERATIONS {
+            term *= x / (a + n as f64);
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        (1.0 - sum * factor).max(0.0)
+    } else {
+        // Continued fraction of Q(a, x), by the modified Lentz method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        (h * factor).min(1.0)
+    }
+}
+
+/// Chi-square test of independence between the rows and columns of a
+/// contingency table.
+pub struct ChiSquare {
+    pub statistic: f64,
+    /// Degrees of freedom.
+    pub df: usize,
+    /// Probability of a statistic at least as large under independence.
+    pub p_value: f64,
+    /// Strength of the association, from 0 to 1.
+    pub cramers_v: f64,
+    /// Number of cells whose expected count is below 5, out of `cells`.
+    pub small_expected: usize,
+    pub cells: usize,
+    /// Smallest expected count.
+    pub min_expected: f64,
+}
+
+impl ChiSquare {
+    /// Whether expected counts are too small for the p-value to be
+    /// trusted: some below 1, or more than a fifth below 5.
+    pub fn is_unreliable(&self) -> bool {
+        self.min_expected < 1.0 || self.small_expected * 5 > self.cells
+    }
+}
+
+/// Runs a chi-square test on a table of counts. Empty rows and columns
+/// are left out. Returns `None` if fewer than two rows or columns remain.
+pub fn chi_square(table: &[Vec<f64>]) -> Option<ChiSquare> {
+    let n_columns = table.iter().map(Vec::len).max().unwrap_or(0);
+    let mut column_totals = vec![0.0; n_columns];
+    for row in table {
+        for (j, &count) in row.iter().enumerate() {
+            column_totals[j] += count;
+        }
+    }
+    let rows: Vec<(&Vec<f64>, f64)> = table.iter()
+        .map(|row| (row, row.iter().sum::<f64>()))
+        .filter(|&(_, total)| total > 0.0)
+        .collect();
+    let columns: Vec<usize> = (0..n_columns).filter(|&j| column_totals[j] > 0.0).collect();
+    if rows.len() < 2 || columns.len() < 2 {
+        return None;
+    }
+
+    let total: f64 = column_totals.iter().sum();
+    let mut statistic = 0.0;
+    let mut small_expected = 0;
+    let mut min_expected = f64::INFINITY;
+    for &(row, row_total) in &rows {
+        for &j in &columns {
+            let expected = row_total * column_totals[j] / total;
+            let observed = row.get(j).cloned().unwrap_or(0.0);
+            statistic += (observed - expected) * (observed - expected) / expected;
+            if expected < 5.0 {
+                small_expected += 1;
+            }
+            min_expected = min_expected.min(expected);
+        }
+    }
+
+    let df = (rows.len() - 1) * (columns.len() - 1);
+    let k = rows.len().min(columns.len()) - 1;
+    Some(ChiSquare {
+        statistic,
+        df,
+        p_value: gamma_q(df as f64 / 2.0, statistic / 2.0),
+        cramers_v: (statistic / (total * k as f64)).sqrt().min(1.0),
+        small_expected,
+        cells: rows.len() * columns.len(),
+        min_expected,
+    })
+}
+
+/// Complementary error function.
+pub fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        2.0 - erfc(-x)
+    } else {
+        gamma_q(0.5, x * x)
+    }
+}
+
+/// Probability that the Kolmogorov distribution exceeds `lambda`.
+pub fn kolmogorov_q(lambda: f64) -> f64 {
+    // The series converges too slowly below, where it is 1 anyway
+    if lambda < 0.2 {
+        return 1.0;
+    }
+    let mut sum = 0.0;
+    let mut sign = 2.0;
+    for j in 1..ITERATIONS {
+        let term = sign * (-2.0 * (j * j) as f64 * lambda * lambda).exp();
+        sum += term;
+        if term.abs() <= EPSILON * sum.abs() {
+            break;
+        }
+        sign = -sign;
+    }
+    sum.clamp(0.0, 1.0)
+}
+
+/// Two-sample tests of whether two sets of values follow the same
+/// distribution.
+pub struct TwoSamples {
+    /// Sizes of the first and second samples.
+    pub sizes: (usize, usize),
+    /// Kolmogorov-Smirnov statistic: the largest distance between the
+    /// cumulative distributions.
+    pub ks: f64,
+    pub ks_p_value: f64,
+    /// Mann-Whitney U of the first sample.
+    pub u: f64,
+    /// Probability that a value of the first sample is larger than one of
+    /// the second, ties counting half: U over the number of pairs.
+    pub auc: f64,
+    /// Two-sided, from the normal approximation corrected for ties.
+    pub u_p_value: f64,
+}
+
+/// Runs two-sample tests on values sorted by value, each marked `true` if
+/// it belongs to the first sample. Returns `None` if a sample is empty.
+pub fn two_samples(sorted: &[(f64, bool)]) -> Option<TwoSamples> {
+    let n1 = sorted.iter().filter(|&&(_, first)| first).count();
+    let n2 = sorted.len() - n1;
+    if n1 == 0 || n2 == 0 {
+        return None;
+    }
+    let (f1, f2) = (n1 as f64, n2 as f64);
+    let n = f1 + f2;
+
+    let mut ks: f64 = 0.0;
+    let mut rank_sum = 0.0;
+    // Sum of t^3 - t over groups of t tied values
+    let mut ties = 0.0;
+    let (mut seen1, mut seen2) = (0, 0);
+    let mut start = 0;
+    while start < sorted.len() {
+        let value = sorted[start].0;
+        // At least one value, should a NaN slip in
+        let count = sorted[start..].iter().take_while(|&&(x, _)| x == value).count();
+        let end = start + count.max(1);
+        let in_first = sorted[start..end].iter().filter(|&&(_, first)| first).count();
+        seen1 += in_first;
+        seen2 += end - start - in_first;
+
+        // Tied values share the average of their ranks, starting at 1
+        let t = (end - start) as f64;
+        rank_sum += in_first as f64 * (start as f64 + (t + 1.0) / 2.0);
+        ties += t * t * t - t;
+        ks = ks.max((seen1 as f64 / f1 - seen2 as f64 / f2).abs());
+        start = end;
+    }
+
+    // Asymptotic distribution, with the usual small sample correction
+    let effective = (f1 * f2 / n).sqrt();
+    let ks_p_value = kolmogorov_q((effective + 0.12 + 0.11 / effective) * ks);
+
+    let u = rank_sum - f1 * (f1 + 1.0) / 2.0;
+    let mean = f1 * f2 / 2.0;
+    let variance = f1 * f2 / 12.0 * ((n + 1.0) - ties / (n * (n - 1.0)));
+    let u_p_value = if variance > 0.0 {
+        // With a continuity correction
+        let z = ((u - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+        erfc(z / ::std::f64::consts::SQRT_2).min(1.0)
+    } else {
+        // All values are equal
+        1.0
+    };
+
+    Some(TwoSamples {
+        sizes: (n1, n2),
+        ks,
+        ks_p_value,
+        u,
+        auc: u / (f1 * f2),
+        u_p_value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance,
+                "{} is not {} within {}",
+                actual,
+                expected,
+                tolerance);
+    }
+
+    #[test]
+    fn gamma_q_known_values() {
+        // Chi-square of 3.841 with one degree of freedom: p = 0.05
+        assert_close(gamma_q(0.5, 3.841 / 2.0), 0.05, 1e-4);
+        assert_close(gamma_q(0.5, 1.92), 0.0500435, 1e-6);
+        // Integer a, by the continued fraction: e^-5 (1 + 5)
+        assert_close(gamma_q(2.0, 5.0), 0.0404277, 1e-6);
+        assert_close(gamma_q(1.0, 0.5), (-0.5f64).exp(), 1e-9);
+        assert_eq!(gamma_q(3.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn kolmogorov_q_known_values() {
+        // The 5% critical value
+        assert_close(kolmogorov_q(1.36), 0.0494859, 1e-6);
+        assert_close(kolmogorov_q(1.0), 0.2699996, 1e-6);
+        assert_eq!(kolmogorov_q(0.1), 1.0);
+        assert!(kolmogorov_q(5.0) < 1e-15);
+    }
+
+    #[test]
+    fn chi_square_known_values() {
+        let test = chi_square(&[vec![10.0, 20.0], vec![30.0, 5.0]]).unwrap();
+        assert_close(test.statistic, 18.7262, 1e-4);
+        assert_eq!(test.df, 1);
+        assert_close(test.p_value, 1.50896e-5, 1e-9);
+        assert_close(test.cramers_v, 0.536745, 1e-6);
+        assert!(!test.is_unreliable());
+
+        // Independent: the rows are proportional
+        let test = chi_square(&[vec![10.0, 20.0], vec![20.0, 40.0]]).unwrap();
+        assert_close(test.statistic, 0.0, 1e-12);
+        assert_close(test.p_value, 1.0, 1e-12);
+    }
+
+    #[test]
+    fn two_samples_known_values() {
+        let a = [1.0, 2.0, 2.0, 3.5, 4.0, 4.0, 5.0, 7.0, 8.0, 9.5];
+        let b = [0.5, 1.0, 2.0, 2.5, 3.0, 3.0, 3.5, 4.0, 4.5, 6.0, 6.5, 0.0];
+        let mut sorted: Vec<(f64, bool)> = a.iter()
+            .map(|&x| (x, true))
+            .chain(b.iter().map(|&x| (x, false)))
+            .collect();
+        sorted.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+        let test = two_samples(&sorted).unwrap();
+        assert_eq!(test.sizes, (10, 12));
+        assert_close(test.ks, 0.3, 1e-12);
+        assert_close(test.ks_p_value, 0.625856, 1e-5);
+        assert_close(test.u, 80.0, 1e-12);
+        assert_close(test.auc, 80.0 / 120.0, 1e-12);
+        assert_close(test.u_p_value, 0.197119, 1e-5);
+    }
+
+    #[test]
+    fn two_samples_edge_cases() {
+        assert!(two_samples(&[(1.0, true), (2.0, true)]).is_none());
+
+        let test = two_samples(&[(1.0, true), (1.0, false)]).unwrap();
+        assert_eq!(test.ks, 0.0);
+        assert_eq!(test.auc, 0.5);
+        assert_eq!(test.u_p_value, 1.0);
+
+        // NaN equals nothing, not even itself, but must not stall the ties
+        let test = two_samples(&[(1.0, true), (f64::NAN, false), (f64::NAN, true)]).unwrap();
+        assert_eq!(test.sizes, (2, 1));
+    }
+}
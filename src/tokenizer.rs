@@ -0,0 +1,403 @@
+// Splits arff lines into tokens, following Weka's quoting rules:
+// tokens may be wrapped in single or double quotes, inside of which
+// backslash escapes are recognized. Outside quotes, spaces and tabs
+// separate tokens, and `%` starts a comment.
+
+use std::borrow::Cow;
+
+/// A token read from a line.
+pub(crate) struct Token<'a> {
+    /// Byte offset of the token in the line.
+    pub(crate) offset: usize,
+    /// Content of the token, with quotes and escapes resolved.
+    pub(crate) text: Cow<'a, str>,
+    /// `true` if the token was quoted in the file.
+    ///
+    /// A quoted `?` is a regular value, not a missing one.
+    pub(crate) quoted: bool,
+}
+
+impl<'a> Token<'a> {
+    /// Returns `true` if this token stands for a missing value.
+    pub(crate) fn is_missing(&self) -> bool {
+        !self.quoted && self.text == "?"
+    }
+}
+
+/// Error found while tokenizing a line.
+pub(crate) struct SyntaxError {
+    /// Byte offset of the problem in the line.
+    pub(crate) offset: usize,
+    pub(crate) message: &'static str,
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+// Characters that end an unquoted word.
+fn is_delimiter(c: char) -> bool {
+    is_blank(c) || c == ',' || c == '{' || c == '}' || c == '%'
+}
+
+/// Reads tokens from a line, one at a time.
+pub(crate) struct Scanner<'a> {
+    line: &'a str,
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    pub(crate) fn new(line: &'a str) -> Self {
+        Scanner { line, pos: 0 }
+    }
+
+    fn skip_blanks(&mut self) {
+        let rest = &self.line[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches(is_blank).len();
+    }
+
+    /// Returns the next non-blank character, without consuming it.
+    ///
+    /// Returns `None` at the end of the line or at the start of a comment.
+    pub(crate) fn peek(&mut self) -> Option<char> {
+        self.skip_blanks();
+        match self.line[self.pos..].chars().next() {
+            Some('%') | None => None,
+            Some(c) => Some(c),
+        }
+    }
+
+    /// Returns `true` if nothing but blanks or a comment remains.
+    pub(crate) fn at_end(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    /// Consumes `c` if it is the next non-blank character.
+    pub(crate) fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes `c`, or fails with the given message.
+    pub(crate) fn expect(&mut self, c: char, message: &'static str)
+                  -> Result<(), SyntaxError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    /// Builds an error at the current position.
+    pub(crate) fn error(&self, message: &'static str) -> SyntaxError {
+        SyntaxError {
+            offset: self.pos,
+            message,
+        }
+    }
+
+    /// Reads a single word, quoted or not.
+    ///
+    /// An unquoted word stops at blanks, commas, braces and comments.
+    pub(crate) fn word(&mut self) -> Result<Token<'a>, SyntaxError> {
+        match self.peek() {
+            Some(q) if q == '\'' || q == '"' => self.quoted(q),
+            Some(c) if !is_delimiter(c) => {
+                let start = self.pos;
+                let rest = &self.line[start..];
+                let len = rest.find(is_delimiter).unwrap_or(rest.len());
+                self.pos += len;
+                Ok(Token {
+                    offset: start,
+                    text: Cow::Borrowed(&rest[..len]),
+                    quoted: false,
+                })
+            }
+            _ => Err(self.error("expected a word")),
+        }
+    }
+
+    /// Reads a comma-separated field, quoted or not.
+    ///
+    /// Unlike `word`, an unquoted field may contain blanks: it runs until
+    /// the next comma, closing brace or comment, minus trailing blanks.
+    pub(crate) fn field(&mut self) -> Result<Token<'a>, SyntaxError> {
+        match self.peek() {
+            Some(q) if q == '\'' || q == '"' => self.quoted(q),
+            _ => {
+                let start = self.pos;
+                let rest = &self.line[start..];
+                let len = rest.find([',', '}', '%'])
+                    .unwrap_or(rest.len());
+                let text = rest[..len].trim_end_matches(is_blank);
+                self.pos += len;
+                Ok(Token {
+                    offset: start,
+                    text: Cow::Borrowed(text),
+                    quoted: false,
+                })
+            }
+        }
+    }
+
+    // Reads a string delimited by `quote`, resolving escapes.
+    fn quoted(&mut self, quote: char) -> Result<Token<'a>, SyntaxError> {
+        let start = self.pos;
+        let body = &self.line[start + 1..];
+
+        // Only allocate if there is something to unescape.
+        let mut escaped: Option<String> = None;
+        let mut chars = body.char_indices();
+        while let Some((i, c)) = chars.next() {
+            if c == quote {
+                self.pos = start + 1 + i + 1;
+                let text = match escaped {
+                    Some(s) => Cow::Owned(s),
+                    None => Cow::Borrowed(&body[..i]),
+                };
+                return Ok(Token {
+                    offset: start,
+                    text,
+                    quoted: true,
+                });
+            }
+
+            if c == '\\' {
+                let s = escaped.get_or_insert_with(|| body[..i].to_string());
+                match chars.next() {
+                    Some((_, e)) => s.push_str(&unescape(e)),
+                    None => break,
+                }
+            } else if let Some(ref mut s) = escaped {
+                s.push(c);
+            }
+        }
+
+        self.pos = start;
+        Err(self.error("unterminated quoted string"))
+    }
+}
+
+// Resolves the character following a backslash.
+fn unescape(c: char) -> Cow<'static, str> {
+    Cow::Borrowed(match c {
+        'n' => "\n",
+        'r' => "\r",
+        't' => "\t",
+        '\\' => "\\",
+        '\'' => "'",
+        '"' => "\"",
+        '%' => "%",
+        c => return Cow::Owned(format!("\\{}", c)),
+    })
+}
+
+/// Fields of a data line, with the instance weight if one was given.
+pub(crate) type Row<'a, T> = (Vec<T>, Option<Token<'a>>);
+
+// Reads an instance weight, such as `{0.25}`, if there is one.
+fn weight<'a>(scanner: &mut Scanner<'a>)
+              -> Result<Option<Token<'a>>, SyntaxError> {
+    if !scanner.eat('{') {
+        return Ok(None);
+    }
+    let weight = scanner.word()?;
+    scanner.expect('}', "expected `}`")?;
+    if !scanner.at_end() {
+        return Err(scanner.error("unexpected text after the weight"));
+    }
+    Ok(Some(weight))
+}
+
+/// Splits a data line into its comma-separated fields.
+pub(crate) fn fields(line: &str) -> Result<Row<'_, Token<'_>>, SyntaxError> {
+    let mut scanner = Scanner::new(line);
+    let mut tokens = Vec::new();
+
+    if scanner.at_end() {
+        return Ok((tokens, None));
+    }
+
+    loop {
+        tokens.push(scanner.field()?);
+        if scanner.at_end() {
+            return Ok((tokens, None));
+        }
+        scanner.expect(',', "expected a comma")?;
+        if scanner.peek() == Some('{') {
+            return Ok((tokens, weight(&mut scanner)?));
+        }
+    }
+}
+
+/// Splits a sparse data line, such as `{0 1.5, 17 yes}`, into pairs of
+/// attribute index and value.
+pub(crate) fn sparse_fields(line: &str)
+                     -> Result<Row<'_, (Token<'_>, Token<'_>)>, SyntaxError> {
+    let mut scanner = Scanner::new(line);
+    let mut pairs = Vec::new();
+
+    scanner.expect('{', "expected `{`")?;
+    if !scanner.eat('}') {
+        loop {
+            let index = scanner.word()?;
+            let value = scanner.field()?;
+            pairs.push((index, value));
+            if scanner.eat('}') {
+                break;
+            }
+            scanner.expect(',', "expected `,` or `}`")?;
+        }
+    }
+
+    if scanner.at_end() {
+        return Ok((pairs, None));
+    }
+    scanner.expect(',', "unexpected text after `}`")?;
+    match weight(&mut scanner)? {
+        Some(weight) => Ok((pairs, Some(weight))),
+        None => Err(scanner.error("expected an instance weight")),
+    }
+}
+
+/// Quotes `s` if needed so that it reads back as a single token.
+pub(crate) fn quote(s: &str) -> Cow<'_, str> {
+    let special = |c: char| {
+        is_delimiter(c) || c == '\'' || c == '"' || c == '\\' ||
+        c == '\n' || c == '\r'
+    };
+    if !s.is_empty() && s != "?" && !s.contains(special) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('\'');
+    for c in s.chars() {
+        match c {
+            '\'' => result.push_str("\\'"),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            '%' => result.push_str("\\%"),
+            c => result.push(c),
+        }
+    }
+    result.push('\'');
+    Cow::Owned(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(line: &str) -> Vec<String> {
+        match fields(line) {
+            Ok((tokens, _)) => tokens.iter().map(|token| token.text.to_string()).collect(),
+            Err(e) => panic!("{} at {}", e.message, e.offset),
+        }
+    }
+
+    fn words(line: &str) -> Vec<String> {
+        let mut scanner = Scanner::new(line);
+        let mut words = Vec::new();
+        while !scanner.at_end() {
+            match scanner.word() {
+                Ok(token) => words.push(token.text.to_string()),
+                Err(e) => panic!("{} at {}", e.message, e.offset),
+            }
+        }
+        words
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(texts("'a b',\"c, d\",e"), ["a b", "c, d", "e"]);
+        assert_eq!(texts("'say \"hi\"',\"it's\""), ["say \"hi\"", "it's"]);
+
+        let (tokens, _) = fields("?,'?',\"?\"").ok().unwrap();
+        let missing: Vec<bool> = tokens.iter().map(Token::is_missing).collect();
+        assert_eq!(missing, [true, false, false]);
+
+        let error = fields("a,'open").err().unwrap();
+        assert_eq!(error.message, "unterminated quoted string");
+        assert_eq!(error.offset, 2);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(texts(r#"'it\'s',"a \"b\"",'back\\slash','tab\there','two\nlines'"#),
+                   ["it's", "a \"b\"", "back\\slash", "tab\there", "two\nlines"]);
+        assert_eq!(texts(r"'50\% off','\q'"), ["50% off", "\\q"]);
+        // Escapes only apply inside quotes
+        assert_eq!(texts(r"a\b"), [r"a\b"]);
+    }
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(words("@attribute\tname  numeric"), ["@attribute", "name", "numeric"]);
+        // Fields keep inner blanks, but not surrounding ones
+        assert_eq!(texts(" a b ,\tc\t, d"), ["a b", "c", "d"]);
+
+        // Braces end unquoted words, and only quoting keeps them in values
+        let mut scanner = Scanner::new("{red,'{blue}'}");
+        assert!(scanner.eat('{'));
+        assert_eq!(scanner.word().ok().unwrap().text, "red");
+        assert!(scanner.eat(','));
+        assert_eq!(scanner.word().ok().unwrap().text, "{blue}");
+        assert!(scanner.eat('}'));
+        assert!(scanner.at_end());
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(texts("1, 2 % the rest is ignored"), ["1", "2"]);
+        assert_eq!(texts("'50%',b%c"), ["50%", "b"]);
+        assert!(texts("   % only a comment").is_empty());
+        assert_eq!(words("@relation test % comment"), ["@relation", "test"]);
+    }
+
+    #[test]
+    fn weights() {
+        let (tokens, weight) = fields("a,'{b}',{0.5}").ok().unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].text, "{b}");
+        assert_eq!(weight.unwrap().text, "0.5");
+
+        assert!(fields("a,{0.5} b").is_err());
+    }
+
+    #[test]
+    fn sparse() {
+        let (pairs, weight) = sparse_fields("{0 1.5, 3 'a }b'},{2}").ok().unwrap();
+        let pairs: Vec<(&str, &str)> = pairs.iter()
+            .map(|(index, value)| (&*index.text, &*value.text))
+            .collect();
+        assert_eq!(pairs, [("0", "1.5"), ("3", "a }b")]);
+        assert_eq!(weight.unwrap().text, "2");
+
+        assert!(sparse_fields("{}").ok().unwrap().0.is_empty());
+        assert!(sparse_fields("{0 1").is_err());
+        assert!(sparse_fields("{0 1} x").is_err());
+    }
+
+    #[test]
+    fn quote_round_trip() {
+        let values = ["plain", "two words", "it's", "say \"hi\"", "back\\slash",
+                      "tab\there", "new\nline", "cr\rhere", "50% off", "a,b", "{x}", "?",
+                      "", " padded "];
+        let line = values.iter().map(|value| quote(value)).collect::<Vec<_>>().join(",");
+        let (tokens, weight) = fields(&line).ok().unwrap();
+        assert!(weight.is_none());
+        let read: Vec<&str> = tokens.iter().map(|token| &*token.text).collect();
+        assert_eq!(read, values);
+        assert!(tokens.iter().all(|token| !token.is_missing()));
+
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("?"), "'?'");
+    }
+}
@@ -1,5 +1,7 @@
 use arff;
 use arff::Population;
+use date;
+use date::TimeUnit;
 use hbs::{Template, HandlebarsEngine, DirectorySource};
 use iron::Handler;
 use iron::prelude::*;
@@ -8,14 +10,17 @@ use mount::Mount;
 // use serde_json;
 use router::Router;
 use staticfile::Static;
-use std::collections::HashMap;
-use std::error::Error;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::mem::transmute;
 use std::path::Path;
 use std::process::Command;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use stats;
 use urlencoded::UrlEncodedQuery;
+use writer;
+use xrff;
 
 fn read_value<T: FromStr>(s: &str) -> Result<T, String>
     where T::Err: Display
@@ -27,21 +32,23 @@ fn read_value<T: FromStr>(s: &str) -> Result<T, String>
 }
 
 fn read_id(s: &str, content: &arff::ArffContent) -> Result<usize, String> {
-    let id: usize = try!(read_value(s));
-    if id >= content.attributes.len() {
+    let id: usize = read_value(s)?;
+    if id >= content.attributes().len() {
         Err(format!("Invalid attribute id! {} > {}",
                     id,
-                    content.attributes.len() - 1))
+                    content.attributes().len() - 1))
     } else {
         Ok(id)
     }
 }
 
 
-fn decorate(slices: Vec<Population>, min: f32, width: f32, i: usize) -> Range {
+fn decorate(slices: Vec<Population>, min: f32, width: f32, i: usize,
+            weight: &dyn Fn(usize) -> f32)
+            -> Range {
     let nmin = min + width * i as f32;
     let nmax = min + width * (i + 1) as f32;
-    Range::new(format!("{}", (nmin + nmax) / 2.0), slices)
+    Range::new(format!("{}", (nmin + nmax) / 2.0), slices, weight)
 }
 
 fn read_or<T: FromStr>(map: &HashMap<String, Vec<String>>, key: &str,
@@ -56,7 +63,7 @@ fn read_or<T: FromStr>(map: &HashMap<String, Vec<String>>, key: &str,
             if list.is_empty() {
                 Ok(default)
             } else {
-                Ok(try!(read_value(&list[0])))
+                read_value(&list[0])
             }
         }
     }
@@ -65,22 +72,17 @@ fn read_or<T: FromStr>(map: &HashMap<String, Vec<String>>, key: &str,
 fn dividers(n: usize) -> Vec<usize> {
     // First get all dividers under the square root
     let mut divs: Vec<_> =
-        (1..).take_while(|k| k * k <= n).filter(|k| n % k == 0).collect();
+        (1..).take_while(|k| k * k <= n).filter(|k| n.is_multiple_of(*k)).collect();
 
     // Then get all the ones above
-    divs.iter()
-        .rev()
-        .map(|k| n / k)
-        .collect::<Vec<usize>>()
-        .iter()
-        .map(|k| divs.push(*k))
-        .collect::<Vec<()>>();
+    let above: Vec<usize> = divs.iter().rev().map(|k| n / k).collect();
+    divs.extend(above);
 
     divs
 }
 
 fn dist(k: usize, n: usize) -> usize {
-    if k > n { k - n } else { n - k }
+    k.abs_diff(n)
 }
 
 fn round_to_divider(value: usize, target: f32) -> usize {
@@ -91,7 +93,7 @@ fn round_to_divider(value: usize, target: f32) -> usize {
 
     // Get the list of the dividers of delta
     let mut divs = dividers(delta);
-    divs.sort_by(|a, b| dist(*a, value).cmp(&dist(*b, value)));
+    divs.sort_by_key(|a| dist(*a, value));
     // Pick one close enough
     let closest = *divs.first().unwrap();
     // println!("Closest divs of {} from {}: {}", delta, value, closest);
@@ -103,16 +105,100 @@ fn round_to_divider(value: usize, target: f32) -> usize {
     }
 }
 
+// Default number of bins of numeric histograms
+const PRECISION: usize = 26;
+
+// Bins of the histogram of a numeric attribute between `min` and `max`:
+// the lower bound of the first bin, the bin width and the number of bins.
+fn histogram_bins(att_type: &arff::AttributeType, min: f32, max: f32, precision: usize)
+                  -> (f32, f32, usize) {
+    let span = max - min;
+    let whole = att_type.is_integer() || att_type.relation().is_some();
+    let (n_slices, width) = if whole {
+        // Bins of a whole width, so that they all hold as many
+        // possible values
+        let n = precision.max(2) - 1;
+        let width = (span / n as f32).ceil().max(1.0);
+        ((span / width).ceil() as usize + 1, width)
+    } else {
+        // round n_slices to a divider of span, if it is a int
+        let n_slices = round_to_divider(precision, span);
+        (n_slices, span / (n_slices - 1) as f32)
+    };
+    (min - width / 2.0, width, n_slices)
+}
+
 #[derive(Serialize)]
 struct PopViewData {
-    lines: Vec<String>,
+    lines: Vec<PopLine>,
     class_description: String,
     description: String,
+    // To export the same population
+    query: String,
+}
+
+// Instances in a slice of the histogram, for a single class.
+struct Selection {
+    class_description: String,
+    description: String,
+    samples: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct PopLine {
+    text: String,
+    // Links to the relational values of the instance
+    bags: Vec<BagLink>,
+}
+
+#[derive(Serialize)]
+struct BagLink {
+    sample: usize,
+    att_id: usize,
+    name: String,
+    size: usize,
+}
+
+fn pop_line(content: &arff::ArffContent, sample: usize) -> PopLine {
+    let bags = content.attributes()
+        .iter()
+        .enumerate()
+        .filter_map(|(att_id, attr)| {
+            content.value(sample, att_id).bag().map(|bag| {
+                BagLink {
+                    sample,
+                    att_id,
+                    name: attr.name.clone(),
+                    size: bag.len(),
+                }
+            })
+        })
+        .collect();
+
+    PopLine {
+        text: content.describe_sample(sample),
+        bags,
+    }
 }
 
 fn prepare_pop_view_data(content: &arff::ArffContent, req: &mut Request)
                          -> Result<PopViewData, String> {
-    let data: AttViewData = try!(prepare_att_view_data(content, req));
+    let selection = select_population(content, req)?;
+    Ok(PopViewData {
+           class_description: selection.class_description,
+           description: selection.description,
+           lines: selection.samples
+               .iter()
+               .map(|&sample| pop_line(content, sample))
+               .collect(),
+           query: req.url.query().unwrap_or("").to_string(),
+       })
+}
+
+fn select_population(content: &arff::ArffContent, req: &mut Request)
+                     -> Result<Selection, String> {
+    // The sidebar order does not matter here
+    let mut data: AttViewData = prepare_att_view_data(content, None, req)?;
     let map = match req.get::<UrlEncodedQuery>() {
         Err(e) => return Err(format!("cannot get query parameters: {}", e)),
         Ok(map) => map,
@@ -123,7 +209,7 @@ fn prepare_pop_view_data(content: &arff::ArffContent, req: &mut Request)
             if ids.is_empty() {
                 0
             } else {
-                try!(read_id(&ids[0], content))
+                read_id(&ids[0], content)?
             }
         }
         None => 0,
@@ -133,21 +219,21 @@ fn prepare_pop_view_data(content: &arff::ArffContent, req: &mut Request)
             if ids.is_empty() {
                 0
             } else {
-                try!(read_id(&ids[0], content))
+                read_id(&ids[0], content)?
             }
         }
-        None => content.attributes.len() - 1,
+        None => content.default_class(),
     };
 
-    let attr = &content.attributes[att_id];
-    let cmp = &content.attributes[att_cmp];
+    let attr = &content.attributes()[att_id];
+    let cmp = &content.attributes()[att_cmp];
 
     let slice_id = match map.get("slice") {
         Some(slice) => {
             if slice.is_empty() {
                 return Err("empty slice parameter".to_string());
             } else {
-                try!(read_id(&slice[0], content))
+                read_id(&slice[0], content)?
             }
         }
         None => return Err("no slice parameter".to_string()),
@@ -172,15 +258,70 @@ fn prepare_pop_view_data(content: &arff::ArffContent, req: &mut Request)
     };
 
 
-    Ok(PopViewData {
+    if slice_id >= data.samples.len() {
+        return Err(format!("no slice {}", slice_id));
+    }
+    let range = data.samples.swap_remove(slice_id);
+    Ok(Selection {
            class_description: format!("{} = {}", cmp.name, class),
-           description: format!("{} ~ {}",
-                                attr.name,
-                                data.samples[slice_id].label),
-           lines: data.samples[slice_id].slices[class_id]
-               .0
-               .iter()
-               .map(|&sample| content.describe_sample(sample))
+           description: format!("{} ~ {}", attr.name, range.label),
+           samples: range.slices
+               .into_iter()
+               .nth(class_id)
+               .map_or(Vec::new(), |population| population.0),
+       })
+}
+
+#[derive(Serialize)]
+struct BagViewData {
+    title: String,
+    name: String,
+    sample: usize,
+    attributes: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+fn prepare_bag_view_data(content: &arff::ArffContent, req: &mut Request)
+                         -> Result<BagViewData, String> {
+    let map = req.get::<UrlEncodedQuery>().unwrap_or_default();
+
+    let att_id = match map.get("att_id").and_then(|ids| ids.first()) {
+        Some(id) => read_id(id, content)?,
+        None => return Err("no att_id parameter".to_string()),
+    };
+    let sample: usize = match map.get("sample").and_then(|s| s.first()) {
+        Some(sample) => read_value(sample)?,
+        None => return Err("no sample parameter".to_string()),
+    };
+    if sample >= content.data.len() {
+        return Err(format!("Invalid sample! {} > {}",
+                           sample,
+                           content.data.len() - 1));
+    }
+
+    let attr = &content.attributes()[att_id];
+    let nested = match attr.att_type.relation() {
+        Some(nested) => nested,
+        None => return Err(format!("{} is not a relational attribute", attr.name)),
+    };
+    let bag = content.value(sample, att_id).bag();
+    let size = bag.map_or(0, |bag| bag.len());
+
+    Ok(BagViewData {
+           title: content.title.clone(),
+           name: attr.name.clone(),
+           sample,
+           attributes: nested.iter().map(|attr| attr.name.clone()).collect(),
+           rows: (0..size)
+               .map(|row| {
+                        nested.iter()
+                            .enumerate()
+                            .map(|(i, attr)| {
+                                     let bag = bag.unwrap();
+                                     attr.att_type.describe(bag.value(nested, row, i))
+                                 })
+                            .collect()
+                    })
                .collect(),
        })
 }
@@ -193,23 +334,333 @@ struct AttViewData {
     filename: String,
     att_id: usize,
     classes: Vec<String>,
-    attributes: Vec<String>,
+    // In the order of the sidebar
+    attributes: Vec<AttributeLink>,
+    // Whether the sidebar is sorted by relevance to the class
+    by_rank: bool,
     samples: Vec<Range>,
+    // Whether ranges follow a numeric or time axis
+    continuous: bool,
     numeric: bool,
+    date: bool,
+    // Relational attributes are shown by bag size
+    relational: bool,
 
     min: Option<f32>,
     max: Option<f32>,
     precision: Option<usize>,
+
+    // Bin width and tick format for dates
+    unit: Option<&'static str>,
+    date_format: Option<&'static str>,
+
+    has_weights: bool,
+    weighted: bool,
+
+    stats: StatsView,
+    // Only for nominal attributes
+    independence: Option<IndependenceView>,
+    // Only for numeric attributes
+    distribution_tests: Vec<DistributionTest>,
+    // Whether classes are compared by pairs rather than to the others
+    pairwise: bool,
+
+    warnings: Vec<WarningGroup>,
+}
+
+#[derive(Serialize)]
+struct StatsView {
+    numeric: bool,
+    nominal: bool,
+    overall: StatsRow,
+    // One row per class of the compared attribute
+    by_class: Vec<StatsRow>,
+}
+
+// Statistics of the attribute for a group of instances, ready to show.
+#[derive(Serialize, Default)]
+struct StatsRow {
+    class: String,
+    missing: String,
+
+    // Numeric attributes
+    min: Option<String>,
+    max: Option<String>,
+    mean: Option<String>,
+    std: Option<String>,
+    q1: Option<String>,
+    median: Option<String>,
+    q3: Option<String>,
+
+    // Nominal attributes
+    cardinality: Option<String>,
+    mode: Option<String>,
+    entropy: Option<String>,
+}
+
+impl StatsRow {
+    // `present` is the weight of the instances with a value, out of `total`.
+    fn new(class: &str, present: f64, total: f64) -> Self {
+        let missing = total - present;
+        let missing = if total > 0.0 {
+            format!("{} ({}%)", stats::format(missing), stats::format(100.0 * missing / total))
+        } else {
+            "0".to_string()
+        };
+        StatsRow {
+            class: class.to_string(),
+            missing,
+            ..Default::default()
+        }
+    }
+
+    // Adds the summary of numeric values, sorted by value.
+    fn numeric(mut self, sorted: &[(f64, f64)]) -> Self {
+        if let Some(summary) = stats::summarize(sorted) {
+            self.min = Some(stats::format(summary.min));
+            self.max = Some(stats::format(summary.max));
+            self.mean = Some(stats::format(summary.mean));
+            self.std = Some(stats::format(summary.std));
+            self.q1 = Some(stats::format(summary.q1));
+            self.median = Some(stats::format(summary.median));
+            self.q3 = Some(stats::format(summary.q3));
+        }
+        self
+    }
+
+    // Adds the summary of nominal values, given the weight of each token.
+    fn nominal(mut self, counts: &[f64], tokens: &[String]) -> Self {
+        let seen = counts.iter().filter(|&&count| count > 0.0).count();
+        self.cardinality = Some(format!("{} of {}", seen, tokens.len()));
+        self.mode = stats::mode(counts)
+            .map(|i| format!("{} ({})", tokens[i], stats::format(counts[i])));
+        self.entropy = Some(format!("{} bits", stats::format(stats::entropy(counts))));
+        self
+    }
 }
 
-fn prepare_att_view_data(content: &arff::ArffContent, req: &mut Request)
+// Computes the statistics of `att_id`, overall and for each class of
+// `att_cmp`.
+fn compute_stats(content: &arff::ArffContent, att_id: usize, att_cmp: usize,
+                 classes: &[String], weight: &dyn Fn(usize) -> f32)
+                 -> StatsView {
+    let class_of = |i: usize| content.value(i, att_cmp).text();
+    let weight = |i: usize| f64::from(weight(i));
+
+    // Weight of all the instances, and of those with a value
+    let mut total = 0.0;
+    let mut present = 0.0;
+    let mut class_total = vec![0.0; classes.len()];
+    let mut class_present = vec![0.0; classes.len()];
+    for i in 0..content.data.len() {
+        let w = weight(i);
+        let has_value = match content.value(i, att_id) {
+            arff::Value::Missing => 0.0,
+            _ => 1.0,
+        };
+        total += w;
+        present += w * has_value;
+        if let Some(k) = class_of(i) {
+            class_total[k] += w;
+            class_present[k] += w * has_value;
+        }
+    }
+
+    let mut overall = StatsRow::new("All", present, total);
+    let mut by_class: Vec<StatsRow> = classes.iter()
+        .enumerate()
+        .map(|(k, class)| StatsRow::new(class, class_present[k], class_total[k]))
+        .collect();
+
+    let mut numeric = false;
+    let mut nominal = false;
+    match *content.samples(att_id) {
+        arff::AttributeSamples::Numeric(ref samples) => {
+            numeric = true;
+            let mut all = Vec::with_capacity(samples.len());
+            let mut per_class = vec![Vec::new(); classes.len()];
+            for &(x, i) in samples {
+                let pair = (f64::from(x), weight(i));
+                all.push(pair);
+                if let Some(k) = class_of(i) {
+                    per_class[k].push(pair);
+                }
+            }
+            overall = overall.numeric(&all);
+            by_class = by_class.into_iter()
+                .zip(per_class)
+                .map(|(row, sorted)| row.numeric(&sorted))
+                .collect();
+        }
+        arff::AttributeSamples::Text(ref groups) => {
+            nominal = true;
+            let tokens = content.attributes()[att_id].att_type.tokens().unwrap_or(&[]);
+            let mut all = vec![0.0; groups.len()];
+            let mut per_class = vec![vec![0.0; groups.len()]; classes.len()];
+            for (t, pop) in groups.iter().enumerate() {
+                for &i in &pop.0 {
+                    all[t] += weight(i);
+                    if let Some(k) = class_of(i) {
+                        per_class[k][t] += weight(i);
+                    }
+                }
+            }
+            overall = overall.nominal(&all, tokens);
+            by_class = by_class.into_iter()
+                .zip(per_class)
+                .map(|(row, counts)| row.nominal(&counts, tokens))
+                .collect();
+        }
+        _ => (),
+    }
+
+    StatsView {
+        numeric,
+        nominal,
+        overall,
+        by_class,
+    }
+}
+
+// Chi-square test of independence between a nominal attribute and the
+// compared one, ready to show.
+#[derive(Serialize)]
+struct IndependenceView {
+    chi_square: String,
+    df: usize,
+    p_value: String,
+    cramers_v: String,
+    // Set when expected counts are too small for the test to hold
+    warning: Option<String>,
+}
+
+impl IndependenceView {
+    // Tests the weights of each class (columns) for each token (rows).
+    fn new(table: &[Vec<f64>]) -> Option<Self> {
+        stats::chi_square(table).map(|test| {
+            let warning = if test.is_unreliable() {
+                Some(format!("Small expected counts ({} of {} below 5, lowest {}): {}",
+                             test.small_expected,
+                             test.cells,
+                             stats::format(test.min_expected),
+                             "the test may not be reliable."))
+            } else {
+                None
+            };
+            IndependenceView {
+                chi_square: stats::format(test.statistic),
+                df: test.df,
+                p_value: stats::format(test.p_value),
+                cramers_v: stats::format(test.cramers_v),
+                warning,
+            }
+        })
+    }
+}
+
+// Two-sample tests between the values of a class and those of another
+// class, or of all the others.
+#[derive(Serialize)]
+struct DistributionTest {
+    class: String,
+    other: String,
+    sizes: String,
+    ks: String,
+    ks_p_value: String,
+    u: String,
+    auc: String,
+    u_p_value: String,
+}
+
+// Compares the distribution of a numeric attribute between each class of
+// `att_cmp` and the others, or between each pair of classes. Instances
+// count once whatever their weight.
+fn compare_classes(content: &arff::ArffContent, samples: &[(f32, usize)], att_cmp: usize,
+                   classes: &[String], pairwise: bool)
+                   -> Vec<DistributionTest> {
+    let class_of = |i: usize| content.value(i, att_cmp).text();
+    let n = classes.len();
+    // With two classes, the others are the other class
+    let pairs: Vec<(usize, Option<usize>)> = if pairwise || n == 2 {
+        (0..n).flat_map(|a| (a + 1..n).map(move |b| (a, Some(b)))).collect()
+    } else {
+        (0..n).map(|a| (a, None)).collect()
+    };
+
+    pairs.into_iter()
+        .filter_map(|(a, b)| {
+            let sorted: Vec<(f64, bool)> = samples.iter()
+                .filter_map(|&(x, i)| {
+                    let k = class_of(i)?;
+                    if k == a {
+                        Some((f64::from(x), true))
+                    } else if b.is_none_or(|b| k == b) {
+                        Some((f64::from(x), false))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            stats::two_samples(&sorted).map(|test| {
+                DistributionTest {
+                    class: classes[a].clone(),
+                    other: b.map_or("others".to_string(), |b| classes[b].clone()),
+                    sizes: format!("{} / {}", test.sizes.0, test.sizes.1),
+                    ks: stats::format(test.ks),
+                    ks_p_value: stats::format(test.ks_p_value),
+                    u: stats::format(test.u),
+                    auc: stats::format(test.auc),
+                    u_p_value: stats::format(test.u_p_value),
+                }
+            })
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct WarningGroup {
+    attribute: String,
+    count: usize,
+    lines: Vec<WarningLine>,
+}
+
+#[derive(Serialize)]
+struct WarningLine {
+    message: String,
+    text: String,
+}
+
+// Lists the load diagnostics by attribute, with the sample lines kept.
+fn summarize_warnings(content: &arff::ArffContent) -> Vec<WarningGroup> {
+    content.diagnostics()
+        .groups()
+        .iter()
+        .map(|group| {
+            WarningGroup {
+                attribute: group.attribute
+                    .clone()
+                    .unwrap_or_else(|| "(whole row)".to_string()),
+                count: group.count,
+                lines: group.samples
+                    .iter()
+                    .map(|diagnostic| {
+                        WarningLine {
+                            message: diagnostic.error.to_string(),
+                            text: diagnostic.text.clone(),
+                        }
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+fn prepare_att_view_data(content: &arff::ArffContent, rankings: Option<&Rankings>,
+                         req: &mut Request)
                          -> Result<AttViewData, String> {
 
     let ueq = req.get::<UrlEncodedQuery>();
-    let hashmap = match ueq {
-        Ok(map) => map,
-        Err(_) => HashMap::new(),
-    };
+    let hashmap = ueq.unwrap_or_default();
 
     // Default to the first attribute
     let att_id = match hashmap.get("att_id") {
@@ -217,26 +668,26 @@ fn prepare_att_view_data(content: &arff::ArffContent, req: &mut Request)
             if ids.is_empty() {
                 0
             } else {
-                try!(read_id(&ids[0], content))
+                read_id(&ids[0], content)?
             }
         }
         None => 0,
     };
 
-    // By default, compares to the last attribute (usually the class)
+    // By default, compares to the class (usually the last attribute)
     let att_cmp = match hashmap.get("att_cmp") {
         Some(ids) => {
             if ids.is_empty() {
                 0
             } else {
-                try!(read_id(&ids[0], content))
+                read_id(&ids[0], content)?
             }
         }
-        None => content.attributes.len() - 1,
+        None => content.default_class(),
     };
 
-    let attr = &content.attributes[att_id];
-    let cmp = &content.attributes[att_cmp];
+    let attr = &content.attributes()[att_id];
+    let cmp = &content.attributes()[att_cmp];
 
     let class_tokens = match cmp.att_type.tokens() {
         Some(tokens) => tokens.to_owned(),
@@ -247,11 +698,21 @@ fn prepare_att_view_data(content: &arff::ArffContent, req: &mut Request)
     };
 
     let mut numeric = false;
+    let mut date = false;
     let mut min = None;
     let mut max = None;
     let mut precision = None;
+    let mut unit = None;
 
-    let ranges: Vec<Range> = match content.samples[att_id] {
+    // Count instances by their weight, unless asked not to
+    let weighted = read_or(&hashmap, "weighted", true)?;
+    let weight = |i: usize| if weighted {
+        content.data.weight(i)
+    } else {
+        1.0
+    };
+
+    let ranges: Vec<Range> = match *content.samples(att_id) {
         arff::AttributeSamples::Numeric(ref samples) => {
             // Numeric attribute. Ranges depend on precision, etc.
             numeric = true;
@@ -259,35 +720,25 @@ fn prepare_att_view_data(content: &arff::ArffContent, req: &mut Request)
             if samples.is_empty() {
                 Vec::new()
             } else {
-                min = Some(try!(read_or(&hashmap, "min", samples[0].0)));
-                max = Some(try!(read_or(&hashmap,
-                                        "max",
-                                        samples[samples.len() - 1].0)));
-
-                let span = max.unwrap() - min.unwrap();
-                // round n_slices to a divider of span, if it is a int
-                precision = Some(try!(read_or(&hashmap, "precision", 26)));
-
-                // Move a bit the precision if it can make things prettier
-                let n_slices = round_to_divider(precision.unwrap(), span);
-
-                let width = span / (n_slices - 1) as f32;
+                min = Some(read_or(&hashmap, "min", samples[0].0)?);
+                max = Some(read_or(&hashmap,
+                                   "max",
+                                   samples[samples.len() - 1].0)?);
+
+                precision = Some(read_or(&hashmap, "precision", PRECISION)?);
+                let (low, width, n_slices) = histogram_bins(&attr.att_type,
+                                                            min.unwrap(),
+                                                            max.unwrap(),
+                                                            precision.unwrap());
                 // println!("max:{} min:{} width:{}", max, min, width);
 
                 // Slice by value
                 // Then group by class
-                rangify(samples,
-                        min.unwrap() - width / 2.0,
-                        max.unwrap() + width / 2.0,
-                        n_slices)
+                rangify(samples, low, low + width * n_slices as f32, n_slices)
                         .iter()
                         .map(|pop| {
                             slice(pop,
-                                  |i| {
-                                      content.data[i].values[att_cmp]
-                                          .text()
-                                          .expect("value is not text!")
-                                  },
+                                  |i| content.value(i, att_cmp).text(),
                                   cmp.att_type
                                       .tokens()
                                       .expect("attribute is not text!")
@@ -295,17 +746,55 @@ fn prepare_att_view_data(content: &arff::ArffContent, req: &mut Request)
                         })
                         .enumerate()
                         .map(|(i, slices)| {
-                                 decorate(slices, min.unwrap(), width, i)
+                                 decorate(slices, low, width, i, &weight)
                              })
                         .collect()
             }
         }
+        arff::AttributeSamples::Date(ref samples) => {
+            // Date attribute. One range per hour, day, week, month or year
+            date = true;
+
+            if samples.is_empty() {
+                Vec::new()
+            } else {
+                let first = samples[0].0;
+                let last = samples[samples.len() - 1].0;
+                let time_unit = match hashmap.get("unit")
+                    .and_then(|u| u.first()) {
+                    Some(u) => {
+                        TimeUnit::parse(u)
+                            .ok_or_else(|| format!("unknown time unit: {}", u))?
+                    }
+                    None => TimeUnit::for_span(last - first),
+                };
+                unit = Some(time_unit);
+
+                let bounds = time_unit.boundaries(first, last);
+                bucketize(samples, &bounds)
+                    .iter()
+                    .map(|pop| {
+                        slice(pop,
+                              |i| content.value(i, att_cmp).text(),
+                              cmp.att_type
+                                  .tokens()
+                                  .unwrap()
+                                  .len())
+                    })
+                    .zip(bounds.iter())
+                    .map(|(slices, &start)| {
+                        let label = date::format_millis(start, "%Y-%m-%d %H:%M");
+                        Range::new(label, slices, &weight)
+                    })
+                    .collect()
+            }
+        }
         arff::AttributeSamples::Text(ref groups) => {
             // Nominal attribute. Simple, one range per attribute value
             groups.iter()
                 .map(|pop| {
                     slice(pop,
-                          |i| content.data[i].values[att_cmp].text().expect("value is not text!!"),
+                          |i| content.value(i, att_cmp).text(),
                           cmp.att_type
                               .tokens()
                               .unwrap()
@@ -313,39 +802,610 @@ fn prepare_att_view_data(content: &arff::ArffContent, req: &mut Request)
                 })
                 .enumerate()
                 .map(|(i, slices)| {
-                         Range::new(format!("{}", attr.att_type.tokens().unwrap()[i]), slices)
+                         Range::new(attr.att_type.tokens().unwrap()[i].to_string(),
+                                    slices,
+                                    &weight)
                      })
                 .collect()
         }
         _ => Vec::new(),
     };
 
+    let stats = compute_stats(content, att_id, att_cmp, &class_tokens, &weight);
+
+    // The ranges of nominal attributes form the contingency table. It counts
+    // instances, whatever their weight, as the distribution tests do.
+    let independence = if attr.att_type.tokens().is_some() && att_id != att_cmp {
+        let table: Vec<Vec<f64>> = ranges.iter()
+            .map(|range| range.slices.iter().map(|pop| pop.0.len() as f64).collect())
+            .collect();
+        IndependenceView::new(&table)
+    } else {
+        None
+    };
+
+    let pairwise = match &*read_or(&hashmap, "versus", "others".to_string())? {
+        "others" => false,
+        "pairs" => true,
+        other => return Err(format!("unknown comparison: {}", other)),
+    };
+    let distribution_tests = match *content.samples(att_id) {
+        arff::AttributeSamples::Numeric(ref samples) => {
+            compare_classes(content, samples, att_cmp, &class_tokens, pairwise)
+        }
+        _ => Vec::new(),
+    };
+
+    // Sidebar order
+    let by_rank = match &*read_or(&hashmap, "order", "index".to_string())? {
+        "index" => false,
+        "rank" => true,
+        other => return Err(format!("unknown order: {}", other)),
+    };
+    let mut order: Vec<usize> = (0..content.attributes().len()).collect();
+    if let (true, Some(rankings)) = (by_rank, rankings) {
+        let ranking = rankings.get(content, att_cmp, class_tokens.len(), weighted);
+        let mut ranked = vec![false; content.attributes().len()];
+        let mut by_relevance: Vec<usize> = ranking.iter()
+            .map(|&(att, _)| {
+                ranked[att] = true;
+                att
+            })
+            .collect();
+        // Then those that were not ranked
+        by_relevance.extend(order.into_iter().filter(|&att| !ranked[att]));
+        order = by_relevance;
+    }
+
     Ok(AttViewData {
            title: content.title.clone(),
            name: attr.name.clone(),
            filename: content.filename.clone(),
-           att_id: att_id,
+           att_id,
            classes: class_tokens,
-           attributes: content.attributes
-               .iter()
-               .map(|attr| attr.name.clone())
+           attributes: order.into_iter()
+               .map(|index| {
+                        AttributeLink {
+                            index,
+                            name: content.attributes()[index].name.clone(),
+                        }
+                    })
                .collect(),
+           by_rank,
            samples: ranges,
+           continuous: numeric || date,
            numeric,
+           date,
+           relational: attr.att_type.relation().is_some(),
            min,
            max,
            precision,
+           unit: unit.map(|u| u.name()),
+           has_weights: content.has_weights(),
+           weighted,
+           date_format: unit.map(|u| u.label_format()),
+           stats,
+           independence,
+           distribution_tests,
+           pairwise,
+           warnings: summarize_warnings(content),
+       })
+}
+
+#[derive(Serialize)]
+struct OverviewData {
+    title: String,
+    filename: String,
+    has_weights: bool,
+    weighted: bool,
+    rows: Vec<OverviewRow>,
+}
+
+// Summary of an attribute, as a row of the overview. The raw values let
+// the page sort rows.
+#[derive(Serialize)]
+struct OverviewRow {
+    index: usize,
+    name: String,
+    type_name: &'static str,
+    missing: String,
+    // Fraction of the weight without a value
+    missing_ratio: f64,
+    distinct: usize,
+
+    // Numeric and date attributes
+    min: Option<String>,
+    max: Option<String>,
+    min_value: Option<f64>,
+    max_value: Option<f64>,
+    // Numeric attributes only
+    mean: Option<String>,
+    mean_value: Option<f64>,
+
+    // Nominal attributes: the most frequent tokens
+    top: Option<String>,
+
+    sparkline: Vec<SparkBar>,
+    // Number of bars, for the width of the sparkline
+    bins: usize,
+}
+
+// A bar of a sparkline, in a box of one unit per bar and of height 1.
+#[derive(Serialize)]
+struct SparkBar {
+    x: usize,
+    y: f32,
+    height: f32,
+}
+
+// Number of tokens listed in the overview of nominal attributes
+const TOP_VALUES: usize = 3;
+
+fn type_name(att_type: &arff::AttributeType) -> &'static str {
+    match *att_type {
+        arff::AttributeType::Numeric => "numeric",
+        arff::AttributeType::Integer => "integer",
+        arff::AttributeType::Date(_) => "date",
+        arff::AttributeType::Text(_) => "nominal",
+        arff::AttributeType::String => "string",
+        arff::AttributeType::Relational(_) => "relational",
+    }
+}
+
+// Scales the weight of each bin to bars of at most 1.
+fn sparkline(pops: &[Population], weight: &dyn Fn(usize) -> f32) -> Vec<SparkBar> {
+    let heights: Vec<f32> = pops.iter()
+        .map(|pop| pop.0.iter().fold(0.0, |sum, &i| sum + weight(i)))
+        .collect();
+    let highest = heights.iter().cloned().fold(0.0, f32::max);
+    heights.into_iter()
+        .enumerate()
+        .map(|(x, height)| {
+            let height = if highest > 0.0 { height / highest } else { 0.0 };
+            SparkBar {
+                x,
+                y: 1.0 - height,
+                height,
+            }
+        })
+        .collect()
+}
+
+// Calls `f` with the samples of an attribute. They are not cached: pages
+// that go through all the attributes would otherwise keep the samples of
+// every one of them.
+fn uncached_samples<T, F>(content: &arff::ArffContent, att: usize, f: F) -> T
+    where F: FnOnce(&arff::AttributeSamples) -> T
+{
+    content.with_samples(att, f)
+}
+
+// Sorts by decreasing key. Stable, so ties keep the declared order.
+fn sort_decreasing<T, F: Fn(&T) -> f64>(items: &mut [T], key: F) {
+    items.sort_by(|a, b| key(b).total_cmp(&key(a)));
+}
+
+fn overview_row(content: &arff::ArffContent, att: usize, weight: &dyn Fn(usize) -> f32)
+                -> OverviewRow {
+    let attr = &content.attributes()[att];
+
+    let mut total = 0.0;
+    let mut present = 0.0;
+    for i in 0..content.data.len() {
+        let w = f64::from(weight(i));
+        total += w;
+        if !matches!(content.value(i, att), arff::Value::Missing) {
+            present += w;
+        }
+    }
+    let missing_ratio = if total > 0.0 { 1.0 - present / total } else { 0.0 };
+
+    let mut row = OverviewRow {
+        index: att,
+        name: attr.name.clone(),
+        type_name: type_name(&attr.att_type),
+        missing: format!("{}%", stats::format(100.0 * missing_ratio)),
+        missing_ratio,
+        distinct: 0,
+        min: None,
+        max: None,
+        min_value: None,
+        max_value: None,
+        mean: None,
+        mean_value: None,
+        top: None,
+        sparkline: Vec::new(),
+        bins: 0,
+    };
+
+    uncached_samples(content, att, |samples| match *samples {
+        arff::AttributeSamples::Numeric(ref samples) => {
+            row.distinct = distinct(samples.iter().map(|&(x, _)| x));
+            let sorted: Vec<(f64, f64)> = samples.iter()
+                .map(|&(x, i)| (f64::from(x), f64::from(weight(i))))
+                .collect();
+            if let Some(summary) = stats::summarize(&sorted) {
+                row.min = Some(stats::format(summary.min));
+                row.max = Some(stats::format(summary.max));
+                row.mean = Some(stats::format(summary.mean));
+                row.min_value = Some(summary.min);
+                row.max_value = Some(summary.max);
+                row.mean_value = Some(summary.mean);
+            }
+            if let (Some(&(min, _)), Some(&(max, _))) = (samples.first(), samples.last()) {
+                let (low, width, n_slices) = histogram_bins(&attr.att_type, min, max, PRECISION);
+                let bins = rangify(samples, low, low + width * n_slices as f32, n_slices);
+                row.sparkline = sparkline(&bins, weight);
+            }
+        }
+        arff::AttributeSamples::Date(ref samples) => {
+            row.distinct = distinct(samples.iter().map(|&(d, _)| d));
+            if let (Some(&(first, _)), Some(&(last, _))) = (samples.first(), samples.last()) {
+                if let arff::AttributeType::Date(ref format) = attr.att_type {
+                    row.min = Some(format.format(first));
+                    row.max = Some(format.format(last));
+                }
+                row.min_value = Some(first as f64);
+                row.max_value = Some(last as f64);
+                let bounds = TimeUnit::for_span(last - first).boundaries(first, last);
+                row.sparkline = sparkline(&bucketize(samples, &bounds), weight);
+            }
+        }
+        arff::AttributeSamples::Text(ref groups) => {
+            let tokens = attr.att_type.tokens().unwrap_or(&[]);
+            row.distinct = groups.iter().filter(|pop| !pop.0.is_empty()).count();
+
+            let counts: Vec<f64> = groups.iter()
+                .map(|pop| pop.0.iter().map(|&i| f64::from(weight(i))).sum())
+                .collect();
+            let mut order: Vec<usize> = (0..counts.len()).filter(|&t| counts[t] > 0.0).collect();
+            sort_decreasing(&mut order, |&t| counts[t]);
+            let top: Vec<String> = order.iter()
+                .take(TOP_VALUES)
+                .map(|&t| format!("{} ({})", tokens[t], stats::format(counts[t])))
+                .collect();
+            row.top = Some(top.join(", "));
+            row.sparkline = sparkline(groups, weight);
+        }
+        arff::AttributeSamples::BadType => {
+            let strings: HashSet<&str> = (0..content.data.len())
+                .filter_map(|i| content.value(i, att).string())
+                .collect();
+            row.distinct = strings.len();
+        }
+    });
+    row.bins = row.sparkline.len();
+
+    row
+}
+
+// Number of distinct values in sorted values.
+fn distinct<T: PartialEq, I: Iterator<Item = T>>(sorted: I) -> usize {
+    let mut count = 0;
+    let mut last = None;
+    for x in sorted {
+        if last.as_ref() != Some(&x) {
+            count += 1;
+            last = Some(x);
+        }
+    }
+    count
+}
+
+fn prepare_overview_data(content: &arff::ArffContent, req: &mut Request)
+                         -> Result<OverviewData, String> {
+    let ueq = req.get::<UrlEncodedQuery>();
+    let hashmap = ueq.unwrap_or_default();
+
+    let weighted = read_or(&hashmap, "weighted", true)?;
+    let weight = |i: usize| if weighted {
+        content.data.weight(i)
+    } else {
+        1.0
+    };
+
+    Ok(OverviewData {
+           title: content.title.clone(),
+           filename: content.filename.clone(),
+           has_weights: content.has_weights(),
+           weighted,
+           rows: (0..content.attributes().len())
+               .map(|att| overview_row(content, att, &weight))
+               .collect(),
+       })
+}
+
+// Weight of each class (columns) among each population (rows).
+fn class_weights(pops: &[Population], class_of: &dyn Fn(usize) -> Option<usize>,
+                 n_classes: usize, weight: &dyn Fn(usize) -> f32)
+                 -> Vec<Vec<f64>> {
+    pops.iter()
+        .map(|pop| {
+            let mut row = vec![0.0; n_classes];
+            for &i in &pop.0 {
+                if let Some(k) = class_of(i) {
+                    row[k] += f64::from(weight(i));
+                }
+            }
+            row
+        })
+        .collect()
+}
+
+// Weight of each class of `att_cmp` (columns) for each bin of `att`
+// (rows), binned as in its histogram. Missing values get a last row of
+// their own. Returns `None` for attributes that cannot be binned.
+fn class_table(content: &arff::ArffContent, att: usize, att_cmp: usize, n_classes: usize,
+               weight: &dyn Fn(usize) -> f32)
+               -> Option<Vec<Vec<f64>>> {
+    let attr = &content.attributes()[att];
+    let class_of = |i: usize| content.value(i, att_cmp).text();
+    let table = uncached_samples(content, att, |samples| match *samples {
+        arff::AttributeSamples::Numeric(ref samples) => {
+            match (samples.first(), samples.last()) {
+                (Some(&(min, _)), Some(&(max, _))) => {
+                    let (low, width, n_slices) = histogram_bins(&attr.att_type, min, max,
+                                                                PRECISION);
+                    let bins = rangify(samples, low, low + width * n_slices as f32, n_slices);
+                    Some(class_weights(&bins, &class_of, n_classes, weight))
+                }
+                _ => Some(Vec::new()),
+            }
+        }
+        arff::AttributeSamples::Date(ref samples) => {
+            match (samples.first(), samples.last()) {
+                (Some(&(first, _)), Some(&(last, _))) => {
+                    let bounds = TimeUnit::for_span(last - first).boundaries(first, last);
+                    Some(class_weights(&bucketize(samples, &bounds), &class_of, n_classes,
+                                       weight))
+                }
+                _ => Some(Vec::new()),
+            }
+        }
+        arff::AttributeSamples::Text(ref groups) => {
+            Some(class_weights(groups, &class_of, n_classes, weight))
+        }
+        arff::AttributeSamples::BadType => None,
+    });
+    let mut table = table?;
+
+    let mut missing = vec![0.0; n_classes];
+    for i in 0..content.data.len() {
+        if let (arff::Value::Missing, Some(k)) = (content.value(i, att), class_of(i)) {
+            missing[k] += f64::from(weight(i));
+        }
+    }
+    table.push(missing);
+    Some(table)
+}
+
+// Attributes that can be binned, other than `att_cmp`, with their
+// relevance to it, by decreasing information gain.
+fn rank_attributes(content: &arff::ArffContent, att_cmp: usize, n_classes: usize,
+                   weight: &dyn Fn(usize) -> f32)
+                   -> Vec<(usize, stats::Relevance)> {
+    let mut ranked: Vec<(usize, stats::Relevance)> = (0..content.attributes().len())
+        .filter(|&att| att != att_cmp)
+        .filter_map(|att| {
+            class_table(content, att, att_cmp, n_classes, weight)
+                .map(|table| (att, stats::relevance(&table)))
+        })
+        .collect();
+    sort_decreasing(&mut ranked, |ranked| ranked.1.info_gain);
+    ranked
+}
+
+// Attributes with their relevance, as returned by `rank_attributes`.
+type Ranking = Arc<Vec<(usize, stats::Relevance)>>;
+
+/// Attribute rankings already computed, by compared attribute and
+/// weighting, shared by the handlers.
+#[derive(Default)]
+struct Rankings {
+    cache: Mutex<HashMap<(usize, bool), Ranking>>,
+}
+
+impl Rankings {
+    // Ranks the attributes against `att_cmp`, unless that was done already.
+    fn get(&self, content: &arff::ArffContent, att_cmp: usize, n_classes: usize,
+           weighted: bool)
+           -> Ranking {
+        if let Some(ranking) = self.cache.lock().unwrap().get(&(att_cmp, weighted)) {
+            return ranking.clone();
+        }
+        let weight = |i: usize| if weighted {
+            content.data.weight(i)
+        } else {
+            1.0
+        };
+        // Computed without the lock, other pages need not wait
+        let ranking = Arc::new(rank_attributes(content, att_cmp, n_classes, &weight));
+        self.cache.lock().unwrap().insert((att_cmp, weighted), ranking.clone());
+        ranking
+    }
+}
+
+// Reads `att_cmp`, which defaults to the class, and its tokens.
+fn read_cmp(content: &arff::ArffContent, hashmap: &HashMap<String, Vec<String>>)
+            -> Result<(usize, Vec<String>), String> {
+    let att_cmp = match hashmap.get("att_cmp").and_then(|ids| ids.first()) {
+        Some(id) => read_id(id, content)?,
+        None => content.default_class(),
+    };
+    let cmp = &content.attributes()[att_cmp];
+    match cmp.att_type.tokens() {
+        Some(tokens) => Ok((att_cmp, tokens.to_owned())),
+        None => Err(format!("Comparison to numeric attributes ({}) not supported", cmp.name)),
+    }
+}
+
+#[derive(Serialize)]
+struct RankingData {
+    title: String,
+    filename: String,
+    att_cmp: usize,
+    class_name: String,
+    // Nominal attributes, that can be compared against instead
+    targets: Vec<AttributeLink>,
+    has_weights: bool,
+    weighted: bool,
+    rows: Vec<RankRow>,
+}
+
+#[derive(Serialize)]
+struct RankRow {
+    rank: usize,
+    index: usize,
+    name: String,
+    type_name: &'static str,
+    info_gain: String,
+    gain_ratio: String,
+    symmetric_uncertainty: String,
+    // Raw values, to sort rows
+    info_gain_value: f64,
+    gain_ratio_value: f64,
+    symmetric_uncertainty_value: f64,
+}
+
+#[derive(Serialize)]
+struct AttributeLink {
+    index: usize,
+    name: String,
+}
+
+fn prepare_ranking_data(content: &arff::ArffContent, rankings: &Rankings,
+                        req: &mut Request)
+                        -> Result<RankingData, String> {
+    let ueq = req.get::<UrlEncodedQuery>();
+    let hashmap = ueq.unwrap_or_default();
+
+    let (att_cmp, classes) = read_cmp(content, &hashmap)?;
+    let weighted = read_or(&hashmap, "weighted", true)?;
+
+    let rows = rankings.get(content, att_cmp, classes.len(), weighted)
+        .iter()
+        .enumerate()
+        .map(|(rank, &(att, ref relevance))| {
+            let attr = &content.attributes()[att];
+            RankRow {
+                rank: rank + 1,
+                index: att,
+                name: attr.name.clone(),
+                type_name: type_name(&attr.att_type),
+                info_gain: stats::format(relevance.info_gain),
+                gain_ratio: stats::format(relevance.gain_ratio),
+                symmetric_uncertainty: stats::format(relevance.symmetric_uncertainty),
+                info_gain_value: relevance.info_gain,
+                gain_ratio_value: relevance.gain_ratio,
+                symmetric_uncertainty_value: relevance.symmetric_uncertainty,
+            }
+        })
+        .collect();
+
+    Ok(RankingData {
+           title: content.title.clone(),
+           filename: content.filename.clone(),
+           att_cmp,
+           class_name: content.attributes()[att_cmp].name.clone(),
+           targets: content.attributes()
+               .iter()
+               .enumerate()
+               .filter(|&(_, attr)| attr.att_type.tokens().is_some())
+               .map(|(index, attr)| AttributeLink { index, name: attr.name.clone() })
+               .collect(),
+           has_weights: content.has_weights(),
+           weighted,
+           rows,
        })
 }
 
 struct AttributeViewHandler {
     content: &'static arff::ArffContent,
+    rankings: Arc<Rankings>,
 }
 
 struct PopViewHandler {
     content: &'static arff::ArffContent,
 }
 
+struct BagViewHandler {
+    content: &'static arff::ArffContent,
+}
+
+struct OverviewHandler {
+    content: &'static arff::ArffContent,
+}
+
+struct RankingHandler {
+    content: &'static arff::ArffContent,
+    rankings: Arc<Rankings>,
+}
+
+struct ExportHandler {
+    content: &'static arff::ArffContent,
+}
+
+impl Handler for ExportHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let selection = match select_population(self.content, req) {
+            Ok(selection) => selection,
+            Err(err) => {
+                return Ok(Response::with((status::Ok, format!("Error: {}", err))))
+            }
+        };
+
+        let format = match req.get::<UrlEncodedQuery>() {
+            Ok(map) => read_or(&map, "format", "arff".to_string()),
+            Err(_) => Ok("arff".to_string()),
+        };
+
+        let mut body = Vec::new();
+        let (written, content_type, filename) = match format.as_ref().map(|f| &**f) {
+            Ok("arff") => {
+                (writer::write_arff(&mut body, self.content, selection.samples),
+                 "text/plain",
+                 "population.arff")
+            }
+            Ok("xrff") => {
+                (xrff::write_xrff(&mut body, self.content, selection.samples),
+                 "application/xml",
+                 "population.xrff")
+            }
+            Ok(other) => {
+                let err = format!("Error: unknown export format: {}", other);
+                return Ok(Response::with((status::Ok, err)));
+            }
+            Err(err) => return Ok(Response::with((status::Ok, format!("Error: {}", err)))),
+        };
+        if let Err(e) = written {
+            return Ok(Response::with((status::InternalServerError, e.to_string())));
+        }
+
+        let mut resp = Response::with((status::Ok, body));
+        resp.headers.set_raw("Content-Type",
+                             vec![format!("{}; charset=utf-8", content_type).into_bytes()]);
+        resp.headers.set_raw("Content-Disposition",
+                             vec![format!("attachment; filename=\"{}\"", filename)
+                                      .into_bytes()]);
+        Ok(resp)
+    }
+}
+
+impl Handler for BagViewHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        match prepare_bag_view_data(self.content, req) {
+            Err(err) => {
+                Ok(Response::with((status::Ok, format!("Error: {}", err))))
+            }
+            Ok(data) => {
+                let mut resp = Response::new();
+                resp.set_mut(Template::new("bag", data)).set_mut(status::Ok);
+                Ok(resp)
+            }
+        }
+    }
+}
+
 impl Handler for PopViewHandler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
         let data = prepare_pop_view_data(self.content, req);
@@ -364,10 +1424,40 @@ impl Handler for PopViewHandler {
     }
 }
 
+impl Handler for OverviewHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        match prepare_overview_data(self.content, req) {
+            Err(err) => {
+                Ok(Response::with((status::Ok, format!("Error: {}", err))))
+            }
+            Ok(data) => {
+                let mut resp = Response::new();
+                resp.set_mut(Template::new("overview", data)).set_mut(status::Ok);
+                Ok(resp)
+            }
+        }
+    }
+}
+
+impl Handler for RankingHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        match prepare_ranking_data(self.content, &self.rankings, req) {
+            Err(err) => {
+                Ok(Response::with((status::Ok, format!("Error: {}", err))))
+            }
+            Ok(data) => {
+                let mut resp = Response::new();
+                resp.set_mut(Template::new("ranking", data)).set_mut(status::Ok);
+                Ok(resp)
+            }
+        }
+    }
+}
+
 impl Handler for AttributeViewHandler {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
 
-        let data = prepare_att_view_data(self.content, req);
+        let data = prepare_att_view_data(self.content, Some(&self.rankings), req);
         match data {
             Err(err) => {
                 Ok(Response::with((status::Ok, format!("Error: {}", err))))
@@ -383,21 +1473,44 @@ impl Handler for AttributeViewHandler {
     }
 }
 
+// The server never returns, so the content outlives the handlers in practice.
+unsafe fn extend_lifetime(content: &arff::ArffContent)
+                          -> &'static arff::ArffContent {
+    transmute::<&arff::ArffContent, &'static arff::ArffContent>(content)
+}
+
 pub fn serve_result<'a>(datadir: &'a str, port: u16,
                         content: &'a arff::ArffContent, open_browser: bool) {
     // Find the resource basedir
     println!("Loading templates from {}", datadir);
 
     let mut router = Router::new();
+    let rankings = Arc::new(Rankings::default());
 
     router.get("/",
                AttributeViewHandler {
-                   content: unsafe { transmute(content) },
+                   content: unsafe { extend_lifetime(content) },
+                   rankings: rankings.clone(),
                },
                "index");
+    router.get("/overview",
+               OverviewHandler { content: unsafe { extend_lifetime(content) } },
+               "overview");
+    router.get("/ranking",
+               RankingHandler {
+                   content: unsafe { extend_lifetime(content) },
+                   rankings,
+               },
+               "ranking");
     router.get("/pop",
-               PopViewHandler { content: unsafe { transmute(content) } },
+               PopViewHandler { content: unsafe { extend_lifetime(content) } },
                "population");
+    router.get("/export",
+               ExportHandler { content: unsafe { extend_lifetime(content) } },
+               "export");
+    router.get("/bag",
+               BagViewHandler { content: unsafe { extend_lifetime(content) } },
+               "bag");
 
     let mut mount = Mount::new();
 
@@ -416,7 +1529,7 @@ pub fn serve_result<'a>(datadir: &'a str, port: u16,
 
     // load templates from all registered sources
     if let Err(r) = hbse.reload() {
-        panic!("{}", r.description());
+        panic!("{}", r);
     }
 
 
@@ -426,9 +1539,8 @@ pub fn serve_result<'a>(datadir: &'a str, port: u16,
 
     if open_browser {
         Command::new("xdg-open")
-            .arg(&format!("http://localhost:{}", port))
+            .arg(format!("http://localhost:{}", port))
             .status()
-            .ok()
             .expect("Could not open page in browser.");
     }
 
@@ -440,23 +1552,29 @@ pub fn serve_result<'a>(datadir: &'a str, port: u16,
 struct Range {
     label: String,
     slices: Vec<Population>,
-    slices_len: Vec<usize>,
+    // Total weight of each slice
+    slices_len: Vec<f32>,
 }
 
 impl Range {
-    fn new(label: String, slices: Vec<Population>) -> Self {
-        let slices_len = slices.iter().map(|pop| pop.0.len()).collect();
+    fn new(label: String, slices: Vec<Population>,
+           weight: &dyn Fn(usize) -> f32)
+           -> Self {
+        let slices_len = slices.iter()
+            .map(|pop| pop.0.iter().map(|&i| weight(i)).sum())
+            .collect();
         Range {
-            label: label,
-            slices: slices,
-            slices_len: slices_len,
+            label,
+            slices,
+            slices_len,
         }
     }
 }
 
-// Slice a population by the given function
+// Slice a population by the given function.
+// Samples for which it returns None (missing class) are left out.
 fn slice<F>(pop: &Population, f: F, n_slices: usize) -> Vec<Population>
-    where F: Fn(usize) -> usize
+    where F: Fn(usize) -> Option<usize>
 {
 
 
@@ -466,12 +1584,39 @@ fn slice<F>(pop: &Population, f: F, n_slices: usize) -> Vec<Population>
     }
 
     for i in pop.0.iter() {
-        slices[f(*i)].0.push(*i);
+        if let Some(k) = f(*i) {
+            slices[k].0.push(*i);
+        }
     }
 
     slices
 }
 
+/// Map sorted (T,usize) to the populations between consecutive bounds
+fn bucketize<T: PartialOrd + Copy>(data: &[(T, usize)], bounds: &[T])
+                                   -> Vec<Population> {
+    if bounds.is_empty() {
+        return Vec::new();
+    }
+
+    let mut result = Vec::with_capacity(bounds.len() - 1);
+    let mut data = data.iter().skip_while(|&&(t, _)| t < bounds[0]).peekable();
+
+    for bound in bounds.iter().skip(1) {
+        let mut list = Vec::new();
+        while let Some(&&(t, i)) = data.peek() {
+            if t >= *bound {
+                break;
+            }
+            list.push(i);
+            data.next();
+        }
+        result.push(Population(list));
+    }
+
+    result
+}
+
 /// Map (f32,usize) by f32 to populations (chunks of usize)
 fn rangify(data: &[(f32, usize)], min: f32, max: f32, slices: usize)
            -> Vec<Population> {
@@ -0,0 +1,171 @@
+// Writing of arff files, so that loaded content can be saved back, whole
+// or in part.
+
+use arff::{ArffContent, Attribute, AttributeType};
+use std::io::{self, Write};
+use tokenizer;
+
+// Writes the declaration of an attribute, with the nested ones for
+// relational attributes.
+fn write_attribute<W: Write>(out: &mut W, attr: &Attribute) -> io::Result<()> {
+    let name = tokenizer::quote(&attr.name);
+    match attr.att_type {
+        AttributeType::Numeric => writeln!(out, "@attribute {} numeric", name),
+        AttributeType::Integer => writeln!(out, "@attribute {} integer", name),
+        AttributeType::String => writeln!(out, "@attribute {} string", name),
+        AttributeType::Date(ref format) => match format.pattern {
+            Some(ref pattern) => {
+                writeln!(out, "@attribute {} date {}", name, tokenizer::quote(pattern))
+            }
+            None => writeln!(out, "@attribute {} date", name),
+        },
+        AttributeType::Text(ref tokens) => {
+            // Keep the declared order, since values refer to it
+            let tokens: Vec<_> = tokens.iter()
+                .map(|token| tokenizer::quote(token))
+                .collect();
+            writeln!(out, "@attribute {} {{{}}}", name, tokens.join(","))
+        }
+        AttributeType::Relational(ref attributes) => {
+            writeln!(out, "@attribute {} relational", name)?;
+            for attr in attributes {
+                write_attribute(out, attr)?;
+            }
+            writeln!(out, "@end {}", name)
+        }
+    }
+}
+
+/// Writes the header of an arff file, up to `@data`.
+pub fn write_header<W: Write>(out: &mut W, title: &str, attributes: &[Attribute])
+                              -> io::Result<()> {
+    writeln!(out, "@relation {}", tokenizer::quote(title))?;
+    writeln!(out)?;
+    for attr in attributes {
+        write_attribute(out, attr)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "@data")
+}
+
+/// Writes the given instances of `content` as an arff file.
+///
+/// Missing values are written as `?`, and sparse data stays sparse.
+pub fn write_arff<W, I>(out: &mut W, content: &ArffContent, samples: I)
+                        -> io::Result<()>
+    where W: Write,
+          I: IntoIterator<Item = usize>
+{
+    write_header(out, &content.title, content.attributes())?;
+    for sample in samples {
+        writeln!(out, "{}", content.data.describe(content.attributes(), sample))?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arff::{ParseMode, Value};
+
+    fn load(text: &[u8]) -> ArffContent {
+        match ArffContent::from_reader(text, "test.arff", ParseMode::Strict) {
+            Ok(content) => content,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn round_trip(content: &ArffContent) -> ArffContent {
+        let mut out = Vec::new();
+        write_arff(&mut out, content, 0..content.len()).unwrap();
+        load(&out)
+    }
+
+    fn assert_same(attributes: &[Attribute], att: usize, a: Value, b: Value) {
+        match (a, b) {
+            (Value::Numeric(x), Value::Numeric(y)) => assert_eq!(x, y),
+            (Value::Date(x), Value::Date(y)) => assert_eq!(x, y),
+            (Value::Text(x), Value::Text(y)) => assert_eq!(x, y),
+            (Value::String(x), Value::String(y)) => assert_eq!(x, y),
+            (Value::Missing, Value::Missing) => (),
+            (Value::Relational(x), Value::Relational(y)) => {
+                let nested = attributes[att].att_type.relation().unwrap();
+                assert_eq!(x.len(), y.len());
+                for i in 0..x.len() {
+                    for n in 0..nested.len() {
+                        assert_same(nested,
+                                    n,
+                                    x.value(nested, i, n),
+                                    y.value(nested, i, n));
+                    }
+                }
+            }
+            _ => panic!("values of `{}` differ", attributes[att].name),
+        }
+    }
+
+    fn assert_same_content(a: &ArffContent, b: &ArffContent) {
+        assert_eq!(a.title, b.title);
+        assert_eq!(a.attributes().len(), b.attributes().len());
+        for (x, y) in a.attributes().iter().zip(b.attributes().iter()) {
+            assert_eq!(x.name, y.name);
+            assert_eq!(x.att_type.tokens(), y.att_type.tokens());
+        }
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.instances().zip(b.instances()) {
+            assert_eq!(x.weight(), y.weight());
+            for att in 0..a.attributes().len() {
+                assert_same(a.attributes(), att, x.value(att), y.value(att));
+            }
+        }
+    }
+
+    #[test]
+    fn dense_round_trip() {
+        let content = load(br#"@relation 'it\'s a test'
+@attribute 'first name' string
+@attribute x numeric
+@attribute colour {red, 'light blue', 'it\'s'}
+@attribute when date
+@attribute day date "dd/MM/yyyy HH:mm"
+@attribute bag relational
+  @attribute y numeric
+  @attribute tag {a, b}
+@end bag
+
+@data
+'O\'Brien, \"Pat\"',1.5,'light blue',2020-01-02T03:04:05.500,'02/01/2020 13:45',"1,a\n2,b",{2}
+'back\\slash\ttab\nline',?,'it\'s',?,?,"",{0.25}
+'a%b',-3,red,2020-01-02T03:04:05,?,"?,?\n4,a"
+?,0,?,1970-01-01,'31/12/1999 00:00',?
+"#);
+        assert_eq!(content.len(), 4);
+        assert_eq!(content.value(0, 0).string(), Some("O'Brien, \"Pat\""));
+        assert_eq!(content.value(1, 0).string(), Some("back\\slash\ttab\nline"));
+        assert!(content.has_weights());
+
+        let written = round_trip(&content);
+        assert_same_content(&content, &written);
+        assert_eq!(written.value(0, 3).date().map(|d| d % 1000), Some(500));
+    }
+
+    #[test]
+    fn sparse_round_trip() {
+        let content = load(br#"@relation sparse
+@attribute a numeric
+@attribute b {x, y}
+@attribute c string
+
+@data
+{0 1, 2 'hello world'}
+{1 y},{3}
+{}
+{0 ?, 1 x, 2 ?}
+"#);
+        assert!(content.data.is_sparse());
+
+        let written = round_trip(&content);
+        assert!(written.data.is_sparse());
+        assert_same_content(&content, &written);
+    }
+}
@@ -0,0 +1,664 @@
+// Reading and writing of XRFF, Weka's XML flavour of arff. On top of the
+// arff content, it tells which attribute is the class.
+
+use arff::{self, ArffContent, ArffError, ArffErrorKind, Attribute, AttributeType,
+           Diagnostic, Diagnostics, ParseMode, Value};
+use column::Table;
+use date::DateFormat;
+use decompress;
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path;
+use std::str::FromStr;
+use tokenizer::Token;
+use xml::attribute::OwnedAttribute;
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Returns `true` if the file should be read as XRFF.
+pub fn is_xrff(filename: &path::Path) -> bool {
+    decompress::format_of(filename) == Some("xrff")
+}
+
+// Element boundaries, the only events the structure depends on.
+enum Tag {
+    Start(String, Vec<OwnedAttribute>),
+    End,
+}
+
+fn attribute<'a>(attrs: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attrs.iter()
+        .find(|attr| attr.name.local_name == name)
+        .map(|attr| &*attr.value)
+}
+
+struct Parser<R: Read> {
+    events: EventReader<R>,
+    filename: String,
+    mode: ParseMode,
+    diagnostics: Diagnostics,
+}
+
+impl<R: Read> Parser<R> {
+    fn line(&self) -> Option<usize> {
+        Some(self.events.position().row as usize + 1)
+    }
+
+    fn error(&self, kind: ArffErrorKind) -> ArffError {
+        ArffError::new(kind).located(&self.filename, self.line())
+    }
+
+    fn bad(&self, what: &str) -> ArffError {
+        self.error(ArffErrorKind::BadXml(what.to_string()))
+    }
+
+    // In strict mode, fails with `error`. In lenient mode, records it.
+    fn report(&mut self, error: ArffError, text: String) -> Result<(), ArffError> {
+        let mut problems = Vec::new();
+        arff::report(self.mode, &mut problems, error)?;
+        for error in problems {
+            self.diagnostics.push(Diagnostic { error, text: text.clone() });
+        }
+        Ok(())
+    }
+
+    // Returns the next element boundary, skipping text and comments.
+    fn tag(&mut self) -> Result<Tag, ArffError> {
+        loop {
+            let event = self.events
+                .next()
+                .map_err(|e| self.bad(&e.to_string()))?;
+            match event {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    return Ok(Tag::Start(name.local_name, attributes))
+                }
+                XmlEvent::EndElement { .. } => return Ok(Tag::End),
+                XmlEvent::EndDocument => return Err(self.bad("unexpected end of document")),
+                _ => (),
+            }
+        }
+    }
+
+    // Skips the rest of the current element.
+    fn skip(&mut self) -> Result<(), ArffError> {
+        let mut depth = 0;
+        loop {
+            match self.tag()? {
+                Tag::Start(..) => depth += 1,
+                Tag::End if depth == 0 => return Ok(()),
+                Tag::End => depth -= 1,
+            }
+        }
+    }
+
+    // Reads the text of the current element, up to its end.
+    fn text(&mut self) -> Result<String, ArffError> {
+        self.token_text().map(|(text, _)| text)
+    }
+
+    // Reads the text of a value, telling whether some of it was in a CDATA
+    // section: such a `?` is a string, not a missing value.
+    fn token_text(&mut self) -> Result<(String, bool), ArffError> {
+        let mut text = String::new();
+        let mut quoted = false;
+        loop {
+            let event = self.events
+                .next()
+                .map_err(|e| self.bad(&e.to_string()))?;
+            match event {
+                XmlEvent::CData(s) => {
+                    quoted = true;
+                    text.push_str(&s)
+                }
+                XmlEvent::Characters(s) |
+                XmlEvent::Whitespace(s) => text.push_str(&s),
+                XmlEvent::EndElement { .. } => return Ok((text, quoted)),
+                XmlEvent::StartElement { .. } => return Err(self.bad("unexpected element in text")),
+                XmlEvent::EndDocument => return Err(self.bad("unexpected end of document")),
+                _ => (),
+            }
+        }
+    }
+
+    // Enters the child element `name`, skipping others before it.
+    fn enter(&mut self, name: &str) -> Result<Vec<OwnedAttribute>, ArffError> {
+        loop {
+            match self.tag()? {
+                Tag::Start(ref tag, ref attrs) if tag == name => return Ok(attrs.clone()),
+                Tag::Start(..) => self.skip()?,
+                Tag::End => return Err(self.bad(&format!("missing <{}>", name))),
+            }
+        }
+    }
+
+    // Reads the attributes declared in an <attributes> element, and the
+    // index of the class among them.
+    fn attributes(&mut self) -> Result<(Vec<Attribute>, Option<usize>), ArffError> {
+        let mut attributes = Vec::new();
+        let mut class = None;
+        loop {
+            match self.tag()? {
+                Tag::Start(ref tag, ref attrs) if tag == "attribute" => {
+                    if attribute(attrs, "class") == Some("yes") {
+                        class = Some(attributes.len());
+                    }
+                    attributes.push(self.attribute(attrs)?);
+                }
+                Tag::Start(..) => self.skip()?,
+                Tag::End => return Ok((attributes, class)),
+            }
+        }
+    }
+
+    fn attribute(&mut self, attrs: &[OwnedAttribute]) -> Result<Attribute, ArffError> {
+        let name = match attribute(attrs, "name") {
+            Some(name) => name.to_string(),
+            None => return Err(self.bad("attribute without a name")),
+        };
+        let mut att_type = match attribute(attrs, "type").unwrap_or("") {
+            "numeric" | "real" => AttributeType::Numeric,
+            "integer" => AttributeType::Integer,
+            "string" => AttributeType::String,
+            "nominal" => AttributeType::Text(Vec::new()),
+            "relational" => AttributeType::Relational(Vec::new()),
+            "date" => match attribute(attrs, "format") {
+                None => AttributeType::Date(DateFormat::iso()),
+                Some(pattern) => match DateFormat::from_pattern(pattern) {
+                    Some(format) => AttributeType::Date(format),
+                    None => {
+                        let mut error = self.error(ArffErrorKind::BadDateFormat);
+                        error.token = Some(pattern.to_string());
+                        return Err(error.with_attribute(&name));
+                    }
+                },
+            },
+            other => {
+                let mut error = self.error(ArffErrorKind::BadType);
+                error.token = Some(other.to_string());
+                return Err(error.with_attribute(&name));
+            }
+        };
+
+        // Labels of nominal attributes, nested attributes of relational ones
+        loop {
+            match self.tag()? {
+                Tag::Start(ref tag, _) if tag == "labels" => {
+                    let mut labels = Vec::new();
+                    loop {
+                        match self.tag()? {
+                            Tag::Start(ref tag, _) if tag == "label" => labels.push(self.text()?),
+                            Tag::Start(..) => self.skip()?,
+                            Tag::End => break,
+                        }
+                    }
+                    if let AttributeType::Text(ref mut tokens) = att_type {
+                        *tokens = labels;
+                    }
+                }
+                Tag::Start(ref tag, _) if tag == "attributes" => {
+                    let (nested, _) = self.attributes()?;
+                    if let AttributeType::Relational(ref mut attributes) = att_type {
+                        *attributes = nested;
+                    }
+                }
+                Tag::Start(..) => self.skip()?,
+                Tag::End => break,
+            }
+        }
+
+        Ok(Attribute { name, att_type })
+    }
+
+    // Reads the <instance> elements of an <instances> element. The table is
+    // created on the first one, sparse or not depending on its type.
+    fn instances(&mut self, attributes: &[Attribute], table: &mut Option<Table>)
+                 -> Result<(), ArffError> {
+        loop {
+            match self.tag()? {
+                Tag::Start(ref tag, ref attrs) if tag == "instance" => {
+                    let sparse = attribute(attrs, "type") == Some("sparse");
+                    let table = table.get_or_insert_with(|| Table::new(attributes, sparse));
+                    self.instance(attributes, table, attrs)?;
+                }
+                Tag::Start(..) => self.skip()?,
+                Tag::End => return Ok(()),
+            }
+        }
+    }
+
+    fn instance(&mut self, attributes: &[Attribute], table: &mut Table,
+                attrs: &[OwnedAttribute])
+                -> Result<(), ArffError> {
+        let sparse = attribute(attrs, "type") == Some("sparse");
+        let weight = match attribute(attrs, "weight") {
+            None => 1.0,
+            Some(text) => match f32::from_str(text) {
+                Ok(weight) if weight >= 0.0 && weight.is_finite() => weight,
+                _ => {
+                    let mut error = self.error(ArffErrorKind::BadWeight);
+                    error.token = Some(text.to_string());
+                    self.report(error, text.to_string())?;
+                    1.0
+                }
+            },
+        };
+
+        let row = table.len();
+        let mut next = 0;
+        loop {
+            let attrs = match self.tag()? {
+                Tag::Start(ref tag, ref attrs) if tag == "value" => attrs.clone(),
+                Tag::Start(..) => {
+                    self.skip()?;
+                    continue;
+                }
+                Tag::End => break,
+            };
+
+            // Sparse indices start at 1
+            let att = if sparse {
+                attribute(&attrs, "index")
+                    .and_then(|index| usize::from_str(index).ok())
+                    .and_then(|index| index.checked_sub(1))
+            } else {
+                Some(next)
+            };
+            let att = match att {
+                Some(att) if att >= next && att < attributes.len() => att,
+                _ if sparse => {
+                    let mut error = self.error(ArffErrorKind::BadIndex);
+                    error.token = attribute(&attrs, "index").map(|index| index.to_string());
+                    return Err(error);
+                }
+                _ => {
+                    let kind = ArffErrorKind::FieldCount {
+                        expected: attributes.len(),
+                        found: next + 1,
+                    };
+                    return Err(self.error(kind));
+                }
+            };
+
+            arff::fill_sparse_defaults(table, attributes, next, att);
+            self.value(&attributes[att], table, att, row)?;
+            next = att + 1;
+        }
+
+        if sparse {
+            arff::fill_sparse_defaults(table, attributes, next, attributes.len());
+        } else if next != attributes.len() {
+            let kind = ArffErrorKind::FieldCount {
+                expected: attributes.len(),
+                found: next,
+            };
+            return Err(self.error(kind));
+        }
+        table.end_row(weight);
+        Ok(())
+    }
+
+    // Reads the content of a <value> element into its column.
+    fn value(&mut self, attr: &Attribute, table: &mut Table, att: usize, row: usize)
+             -> Result<(), ArffError> {
+        if let AttributeType::Relational(ref nested) = attr.att_type {
+            // A missing bag is written `?`, or has no <instances> at all
+            let mut bag = None;
+            let mut found = false;
+            loop {
+                match self.tag()? {
+                    Tag::Start(ref tag, _) if tag == "instances" => {
+                        self.instances(nested, &mut bag)?;
+                        found = true;
+                    }
+                    Tag::Start(..) => self.skip()?,
+                    Tag::End => break,
+                }
+            }
+            if !found {
+                table.column_mut(att).push_missing(row);
+                return Ok(());
+            }
+            let bag = bag.unwrap_or_else(|| Table::new(nested, false));
+            if bag.is_sparse() {
+                // Bags share the dense storage of their column
+                return Err(self.bad("sparse instances in relational values"));
+            }
+            table.column_mut(att).push_bag(row, bag);
+            return Ok(());
+        }
+
+        let (text, quoted) = self.token_text()?;
+        let token = Token {
+            offset: 0,
+            text: Cow::Borrowed(&text),
+            quoted,
+        };
+        let column = table.column_mut(att);
+        if let Err(kind) = attr.att_type.parse_value(&token, column, row) {
+            column.push_missing(row);
+            let mut error = self.error(kind).with_attribute(&attr.name);
+            error.token = Some(text.clone());
+            self.report(error, text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads a XRFF file, compressed or not.
+pub fn load(filename: &path::Path, mode: ParseMode) -> Result<ArffContent, ArffError> {
+    let name = filename.display().to_string();
+    let file = fs::File::open(filename).map_err(|e| {
+        ArffError::new(ArffErrorKind::Io(e)).located(&name, None)
+    })?;
+    from_reader(file, &name, mode)
+}
+
+/// Loads XRFF content from any reader.
+pub fn from_reader<R: Read>(reader: R, name: &str, mode: ParseMode)
+                            -> Result<ArffContent, ArffError> {
+    let reader = decompress::decoder(io::BufReader::new(reader))
+        .map_err(|e| ArffError::new(ArffErrorKind::Io(e)).located(name, None))?;
+    let mut parser = Parser {
+        events: EventReader::new(io::BufReader::new(reader)),
+        filename: name.to_string(),
+        mode,
+        diagnostics: Diagnostics::new(),
+    };
+
+    let dataset = parser.enter("dataset")?;
+    let title = attribute(&dataset, "name").unwrap_or("").to_string();
+
+    parser.enter("header")?;
+    parser.enter("attributes")?;
+    let (attributes, class) = parser.attributes()?;
+    parser.skip()?;
+
+    parser.enter("body")?;
+    parser.enter("instances")?;
+    let mut data = None;
+    parser.instances(&attributes, &mut data)?;
+    let data = data.unwrap_or_else(|| Table::new(&attributes, false));
+
+    let mut content = ArffContent::from_table(name, &title, attributes, data);
+    content.set_class_attribute(class)?;
+    content.set_diagnostics(parser.diagnostics);
+    Ok(content)
+}
+
+// Escapes text for use in XML content and attribute values.
+fn escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+    let mut result = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            c => result.push(c),
+        }
+    }
+    Cow::Owned(result)
+}
+
+fn write_attributes<W: Write>(out: &mut W, attributes: &[Attribute],
+                              class: Option<usize>, indent: &str)
+                              -> io::Result<()> {
+    writeln!(out, "{}<attributes>", indent)?;
+    for (att, attr) in attributes.iter().enumerate() {
+        let marker = if class == Some(att) { "class=\"yes\" " } else { "" };
+        let name = escape(&attr.name);
+        let att_type = match attr.att_type {
+            AttributeType::Numeric | AttributeType::Integer => "numeric",
+            AttributeType::Date(_) => "date",
+            AttributeType::Text(_) => "nominal",
+            AttributeType::String => "string",
+            AttributeType::Relational(_) => "relational",
+        };
+        write!(out, "{}  <attribute {}name=\"{}\" type=\"{}\"", indent, marker, name, att_type)?;
+        if let AttributeType::Date(DateFormat { pattern: Some(ref pattern), .. }) = attr.att_type {
+            write!(out, " format=\"{}\"", escape(pattern))?;
+        }
+
+        match attr.att_type {
+            AttributeType::Text(ref tokens) => {
+                writeln!(out, ">")?;
+                writeln!(out, "{}    <labels>", indent)?;
+                for token in tokens {
+                    writeln!(out, "{}      <label>{}</label>", indent, escape(token))?;
+                }
+                writeln!(out, "{}    </labels>", indent)?;
+                writeln!(out, "{}  </attribute>", indent)?;
+            }
+            AttributeType::Relational(ref nested) => {
+                writeln!(out, ">")?;
+                write_attributes(out, nested, None, &format!("{}    ", indent))?;
+                writeln!(out, "{}  </attribute>", indent)?;
+            }
+            _ => writeln!(out, "/>")?,
+        }
+    }
+    writeln!(out, "{}</attributes>", indent)
+}
+
+// Writes a value as XRFF text: as in arff, but without quotes.
+fn value_text(att_type: &AttributeType, value: Value) -> String {
+    match value {
+        Value::Date(d) => match *att_type {
+            AttributeType::Date(ref format) => format.format(d),
+            _ => d.to_string(),
+        },
+        Value::Text(i) => match att_type.tokens() {
+            Some(tokens) => tokens[i].clone(),
+            None => "?".to_string(),
+        },
+        Value::String(s) => s.to_string(),
+        _ => att_type.describe(value),
+    }
+}
+
+fn write_instances<W: Write>(out: &mut W, table: &Table, attributes: &[Attribute],
+                             rows: &mut dyn Iterator<Item = usize>, indent: &str)
+                             -> io::Result<()> {
+    writeln!(out, "{}<instances>", indent)?;
+    for row in rows {
+        write!(out, "{}  <instance", indent)?;
+        if table.is_sparse() {
+            write!(out, " type=\"sparse\"")?;
+        }
+        if table.weight(row) != 1.0 {
+            write!(out, " weight=\"{}\"", table.weight(row))?;
+        }
+        writeln!(out, ">")?;
+
+        for (att, attr) in attributes.iter().enumerate() {
+            let value = match table.column(att).get(row) {
+                Some(value) => value,
+                // Left out of a sparse instance
+                None => continue,
+            };
+            write!(out, "{}    <value", indent)?;
+            if table.is_sparse() {
+                write!(out, " index=\"{}\"", att + 1)?;
+            }
+            match (value, attr.att_type.relation()) {
+                (Value::Relational(bag), Some(nested)) => {
+                    writeln!(out, ">")?;
+                    write_instances(out,
+                                    bag.table(),
+                                    nested,
+                                    &mut bag.rows(),
+                                    &format!("{}      ", indent))?;
+                    writeln!(out, "{}    </value>", indent)?;
+                }
+                // Told apart from a missing value by the reader
+                (Value::String("?"), _) => writeln!(out, "><![CDATA[?]]></value>")?,
+                _ => {
+                    let text = value_text(&attr.att_type, value);
+                    writeln!(out, ">{}</value>", escape(&text))?;
+                }
+            }
+        }
+        writeln!(out, "{}  </instance>", indent)?;
+    }
+    writeln!(out, "{}</instances>", indent)
+}
+
+/// Writes the given instances of `content` as a XRFF document, marking the
+/// class attribute if known.
+pub fn write_xrff<W, I>(out: &mut W, content: &ArffContent, samples: I) -> io::Result<()>
+    where W: Write,
+          I: IntoIterator<Item = usize>
+{
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+    writeln!(out, "<dataset name=\"{}\">", escape(&content.title))?;
+    writeln!(out, "  <header>")?;
+    write_attributes(out, content.attributes(), content.class_attribute(), "    ")?;
+    writeln!(out, "  </header>")?;
+    writeln!(out, "  <body>")?;
+    write_instances(out,
+                    &content.data,
+                    content.attributes(),
+                    &mut samples.into_iter(),
+                    "    ")?;
+    writeln!(out, "  </body>")?;
+    writeln!(out, "</dataset>")?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use writer;
+
+    fn arff(text: &str) -> ArffContent {
+        match ArffContent::from_reader(text.as_bytes(), "test.arff", ParseMode::Strict) {
+            Ok(content) => content,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn read(text: &[u8], mode: ParseMode) -> ArffContent {
+        match from_reader(text, "test.xrff", mode) {
+            Ok(content) => content,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn round_trip(content: &ArffContent) -> ArffContent {
+        let mut out = Vec::new();
+        write_xrff(&mut out, content, 0..content.len()).unwrap();
+        read(&out, ParseMode::Strict)
+    }
+
+    // Content written as arff, which tells missing values from the string `?`
+    fn as_arff(content: &ArffContent) -> String {
+        let mut out = Vec::new();
+        writer::write_arff(&mut out, content, 0..content.len()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn dense_round_trip() {
+        let mut content = arff("@relation test\n\
+                                @attribute name string\n\
+                                @attribute x numeric\n\
+                                @attribute when date 'yyyy-MM-dd'\n\
+                                @attribute bag relational\n\
+                                @attribute y numeric\n\
+                                @attribute tag {a,'b c'}\n\
+                                @end bag\n\
+                                @attribute class {yes,no}\n\
+                                @data\n\
+                                '<a & b>',1.5,2020-01-02,'1,a\\n?,\\'b c\\'',yes\n\
+                                '?',?,?,?,no,{2.5}\n\
+                                ?,-2,1999-12-31,'',?,{0}\n");
+        content.set_class_attribute(Some(4)).unwrap();
+
+        let written = round_trip(&content);
+        assert_eq!(written.class_attribute(), Some(4));
+        assert_eq!(as_arff(&written), as_arff(&content));
+        assert_eq!(written.value(1, 0).string(), Some("?"));
+        assert!(matches!(written.value(1, 3), Value::Missing));
+        assert!(matches!(written.value(2, 0), Value::Missing));
+        assert_eq!(written.value(2, 3).bag().map(|bag| bag.len()), Some(0));
+        assert_eq!(written.instance(1).weight(), 2.5);
+        assert_eq!(written.instance(2).weight(), 0.0);
+    }
+
+    #[test]
+    fn sparse_round_trip() {
+        let content = arff("@relation sparse\n\
+                            @attribute a numeric\n\
+                            @attribute b {x,y}\n\
+                            @attribute c string\n\
+                            @data\n\
+                            {0 1,2 'hello world'}\n\
+                            {1 y},{3}\n\
+                            {}\n\
+                            {0 ?,2 ?}\n");
+
+        let written = round_trip(&content);
+        assert!(written.data.is_sparse());
+        assert_eq!(written.class_attribute(), None);
+        assert_eq!(as_arff(&written), as_arff(&content));
+    }
+
+    #[test]
+    fn reads_missing_bags() {
+        let text = br#"<?xml version="1.0" encoding="utf-8"?>
+<dataset name="bags">
+  <header>
+    <attributes>
+      <attribute name="bag" type="relational">
+        <attributes><attribute name="x" type="numeric"/></attributes>
+      </attribute>
+      <attribute class="yes" name="class" type="nominal">
+        <labels><label>a</label><label>b</label></labels>
+      </attribute>
+    </attributes>
+  </header>
+  <body>
+    <instances>
+      <instance><value>?</value><value>a</value></instance>
+      <instance><value/><value>b</value></instance>
+      <instance>
+        <value><instances><instance><value>3</value></instance></instances></value>
+        <value>?</value>
+      </instance>
+    </instances>
+  </body>
+</dataset>
+"#;
+        let content = read(text, ParseMode::Lenient);
+        assert!(content.diagnostics().is_empty());
+        assert_eq!(content.class_attribute(), Some(1));
+        assert_eq!(content.len(), 3);
+        assert!(matches!(content.value(0, 0), Value::Missing));
+        assert!(matches!(content.value(1, 0), Value::Missing));
+        assert_eq!(content.value(2, 0).bag().map(|bag| bag.len()), Some(1));
+        assert!(matches!(content.value(2, 1), Value::Missing));
+    }
+
+    #[test]
+    fn lenient_keeps_bad_values_missing() {
+        let text = br#"<dataset name="bad">
+  <header><attributes><attribute name="x" type="numeric"/></attributes></header>
+  <body><instances>
+    <instance><value>oops</value></instance>
+    <instance weight="-1"><value>2</value></instance>
+  </instances></body>
+</dataset>"#;
+        assert!(from_reader(&text[..], "test.xrff", ParseMode::Strict).is_err());
+
+        let content = read(text, ParseMode::Lenient);
+        assert_eq!(content.len(), 2);
+        assert_eq!(content.diagnostics().len(), 2);
+        assert!(matches!(content.value(0, 0), Value::Missing));
+        assert_eq!(content.instance(1).weight(), 1.0);
+    }
+}